@@ -0,0 +1,280 @@
+//! # Asynchronous I/O
+//!
+//! Adapters which read and write packets on Tokio streams.  They mirror
+//! `Decoder::decode_from` and `Encoder::encode_into` and wrap a `Decoder` or
+//! `Encoder` so that its byte order and other settings are kept.
+
+extern crate tokio;
+
+use self::tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+use std::cmp;
+use std::future::Future;
+use std::io;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use hdr::CqcHdr;
+use {DecodeError, Decoder, Encoder};
+
+/// The largest number of bytes the buffer grows by for a single read.  The
+/// body is read in chunks so that a corrupt length in the CQC Header does not
+/// allocate the whole body up front.
+const CHUNK_LEN: usize = 4096;
+
+/// # Asynchronous packet decoder
+///
+/// Reads packets from an `AsyncRead` such as a Tokio `TcpStream`.
+pub struct AsyncDecoder {
+    decoder: Decoder,
+}
+
+impl AsyncDecoder {
+    /// Create a big endian `AsyncDecoder`.
+    pub fn new() -> AsyncDecoder {
+        AsyncDecoder::from(Decoder::new())
+    }
+
+    /// Read a single packet from a reader and decode it.
+    ///
+    /// The CQC Header is read first to learn the length of the body.  Then
+    /// exactly that many bytes are read so that no bytes of the next packet
+    /// are consumed.
+    pub fn decode_from<'a, R, T>(
+        &'a self,
+        reader: &'a mut R,
+    ) -> impl Future<Output = Result<T, DecodeError>> + 'a
+    where
+        R: AsyncRead + Unpin,
+        T: DeserializeOwned + 'a,
+    {
+        DecodeFrom {
+            decoder: &self.decoder,
+            reader,
+            buffer: Vec::new(),
+            target: CqcHdr::hdr_len() as usize,
+            have_hdr: false,
+            packet: PhantomData,
+        }
+    }
+}
+
+impl Default for AsyncDecoder {
+    fn default() -> AsyncDecoder {
+        AsyncDecoder::new()
+    }
+}
+
+impl From<Decoder> for AsyncDecoder {
+    fn from(decoder: Decoder) -> AsyncDecoder {
+        AsyncDecoder { decoder }
+    }
+}
+
+/// Future returned by `AsyncDecoder::decode_from`.
+struct DecodeFrom<'a, R: 'a, T> {
+    decoder: &'a Decoder,
+    reader: &'a mut R,
+    buffer: Vec<u8>,
+    target: usize,
+    have_hdr: bool,
+    packet: PhantomData<fn() -> T>,
+}
+
+impl<'a, R, T> Future for DecodeFrom<'a, R, T>
+where
+    R: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    type Output = Result<T, DecodeError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+
+        loop {
+            let start = this.buffer.len();
+            if start < this.target {
+                let chunk = cmp::min(this.target - start, CHUNK_LEN);
+                this.buffer.resize(start + chunk, 0);
+
+                let mut buf = ReadBuf::new(&mut this.buffer[start..]);
+                let reader = Pin::new(&mut *this.reader);
+                let result = reader.poll_read(cx, &mut buf);
+                let n = buf.filled().len();
+                this.buffer.truncate(start + n);
+
+                match result {
+                    Poll::Ready(Ok(())) => {}
+                    Poll::Ready(Err(err)) => {
+                        return Poll::Ready(Err(io_err(err)))
+                    }
+                    Poll::Pending => return Poll::Pending,
+                }
+
+                if n == 0 {
+                    let err = io::ErrorKind::UnexpectedEof.into();
+                    return Poll::Ready(Err(io_err(err)));
+                }
+                continue;
+            }
+
+            if this.have_hdr {
+                return Poll::Ready(this.decoder.decode(&this.buffer[..]));
+            }
+
+            // The whole CQC Header has arrived so the body length is known.
+            let cqc_hdr = match this.decoder.decode_cqc_hdr(&this.buffer[..]) {
+                Ok(cqc_hdr) => cqc_hdr,
+                Err(err) => return Poll::Ready(Err(err)),
+            };
+            this.target = start + cqc_hdr.length as usize;
+            this.have_hdr = true;
+        }
+    }
+}
+
+/// Report an I/O error in the same way as `Decoder::decode_from`.
+fn io_err(err: io::Error) -> DecodeError {
+    bincode::Error::from(err).into()
+}
+
+/// # Asynchronous packet encoder
+///
+/// Writes packets to an `AsyncWrite` such as a Tokio `TcpStream`.
+pub struct AsyncEncoder {
+    encoder: Encoder,
+}
+
+impl AsyncEncoder {
+    /// Create a big endian `AsyncEncoder`.
+    pub fn new() -> AsyncEncoder {
+        AsyncEncoder::from(Encoder::new())
+    }
+
+    /// Encode a CQC packet and write it to a writer.
+    ///
+    /// The packet is encoded in full before any bytes are written.
+    pub fn encode_into<'a, W, T>(
+        &self,
+        packet: &T,
+        writer: &'a mut W,
+    ) -> impl Future<Output = io::Result<()>> + 'a
+    where
+        W: AsyncWrite + Unpin,
+        T: Serialize,
+    {
+        EncodeInto {
+            writer,
+            buffer: self.encoder.into_vec(packet),
+            written: 0,
+        }
+    }
+}
+
+impl Default for AsyncEncoder {
+    fn default() -> AsyncEncoder {
+        AsyncEncoder::new()
+    }
+}
+
+impl From<Encoder> for AsyncEncoder {
+    fn from(encoder: Encoder) -> AsyncEncoder {
+        AsyncEncoder { encoder }
+    }
+}
+
+/// Future returned by `AsyncEncoder::encode_into`.
+struct EncodeInto<'a, W: 'a> {
+    writer: &'a mut W,
+    buffer: Vec<u8>,
+    written: usize,
+}
+
+impl<'a, W> Future for EncodeInto<'a, W>
+where
+    W: AsyncWrite + Unpin,
+{
+    type Output = io::Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+
+        while this.written < this.buffer.len() {
+            let buf = &this.buffer[this.written..];
+            let n = match Pin::new(&mut *this.writer).poll_write(cx, buf) {
+                Poll::Ready(Ok(n)) => n,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            this.written += n;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use builder::{Client, Server};
+    use hdr::*;
+    use std::task::Waker;
+    use {Request, Response};
+
+    /// Poll a future which never waits, as is the case for in-memory readers
+    /// and writers, to completion.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let request = Client::new(10).cmd_new(1, CmdOpt::empty());
+        let response = Server::new(10).new_ok(1);
+
+        let encoder = AsyncEncoder::new();
+        let mut buffer: Vec<u8> = Vec::new();
+        block_on(encoder.encode_into(&request, &mut buffer)).unwrap();
+        block_on(encoder.encode_into(&response, &mut buffer)).unwrap();
+        assert_eq!(
+            buffer.len(),
+            request.len() as usize + response.len() as usize
+        );
+
+        let decoder = AsyncDecoder::new();
+        let mut reader = &buffer[..];
+        let decoded: Request =
+            block_on(decoder.decode_from(&mut reader)).unwrap();
+        assert_eq!(decoded, request);
+        let decoded: Response =
+            block_on(decoder.decode_from(&mut reader)).unwrap();
+        assert_eq!(decoded, response);
+        assert!(reader.is_empty());
+
+        // A truncated packet cannot be read.
+        let mut reader = &buffer[..request.len() as usize - 1];
+        let decoded: Result<Request, _> =
+            block_on(decoder.decode_from(&mut reader));
+        assert!(decoded.is_err());
+
+        // A corrupt length is not trusted to size the buffer.
+        let mut packet = Encoder::new().into_vec(&request);
+        packet[4..8].copy_from_slice(&[0xFF; 4]);
+        let mut reader = &packet[..];
+        let decoded: Result<Request, _> =
+            block_on(decoder.decode_from(&mut reader));
+        assert!(decoded.is_err());
+    }
+}
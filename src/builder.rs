@@ -10,8 +10,23 @@
 //! This module is to be used in conjunction with the CQC interface
 //! documentation available in the `hdr` module.
 
+use error::BuildError;
+#[cfg(not(feature = "std"))]
+use alloc::collections::BTreeSet;
 use hdr::*;
-use {ReqCmd, Request, XtraHdr, RspInfo, Response, EprInfo};
+#[cfg(not(feature = "std"))]
+use alloc_prelude::*;
+#[cfg(feature = "std")]
+use std::collections::BTreeSet;
+use std::marker::PhantomData;
+#[cfg(feature = "std")]
+use std::net::SocketAddrV4;
+use std::sync::atomic::{AtomicU16, Ordering};
+#[cfg(feature = "std")]
+use std::time::SystemTime;
+#[cfg(feature = "std")]
+use Encoder;
+use {FactoryReq, IfReq, MixSeg, ReqBody, ReqCmd, Request, XtraHdr, RspInfo, Response, EprInfo};
 
 /// Struct containing all the necessary bits of information to identify a
 /// remote instance of the CQC backend.
@@ -22,49 +37,238 @@ pub struct RemoteId {
     pub remote_port: u16,
 }
 
+/// The single-qubit Pauli operators.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Pauli {
+    I,
+    X,
+    Y,
+    Z,
+}
+
+impl From<Pauli> for Cmd {
+    fn from(pauli: Pauli) -> Self {
+        match pauli {
+            Pauli::I => Cmd::I,
+            Pauli::X => Cmd::X,
+            Pauli::Y => Cmd::Y,
+            Pauli::Z => Cmd::Z,
+        }
+    }
+}
+
+/// The axes of the single-qubit rotations.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl From<Axis> for Cmd {
+    fn from(axis: Axis) -> Self {
+        match axis {
+            Axis::X => Cmd::RotX,
+            Axis::Y => Cmd::RotY,
+            Axis::Z => Cmd::RotZ,
+        }
+    }
+}
+
+/// Conversion of a user type into a CQC request.  This allows types such as
+/// a gate from another intermediate representation to be passed directly to
+/// `Encoder::encode_as`.
+pub trait ToRequest {
+    /// Build the request using the given client.
+    fn to_request(&self, client: &Client) -> Request;
+}
+
+/// Conversion of a user type into a CQC response.
+pub trait ToResponse {
+    /// Build the response using the given server.
+    fn to_response(&self, server: &Server) -> Response;
+}
+
 /// The Client builder constructs requests for a particular application ID.
 pub struct Client {
     app_id: u16,
+    token_bits: u8,
+    token: AtomicU16,
 }
 
 impl Client {
     /// Construct a Client builder.
     #[inline]
     pub fn new(app_id: u16) -> Self {
-        Client { app_id }
+        Client::with_correlation(app_id, 0)
+    }
+
+    /// Construct a Client builder that tags every request with a correlation
+    /// token so that responses can be matched to requests over a multiplexed
+    /// connection.
+    ///
+    /// The application ID field is split in two.  The top `token_bits` bits
+    /// hold the token and the remaining low bits hold `app_id`.  The token
+    /// starts at 0 and is incremented for every request built, wrapping
+    /// around once it no longer fits in `token_bits` bits.  The backend
+    /// echoes the application ID so `Response::correlation` recovers the
+    /// token from a response.
+    ///
+    /// Panics if `token_bits` is 16 or more or if `app_id` does not fit in
+    /// the remaining low bits.
+    pub fn with_correlation(app_id: u16, token_bits: u8) -> Self {
+        assert!(token_bits < 16, "At most 15 bits may hold the token");
+        assert!(
+            u32::from(app_id) < (1 << (16 - token_bits)),
+            "Application ID does not fit in {} bits",
+            16 - token_bits
+        );
+
+        Client {
+            app_id,
+            token_bits,
+            token: AtomicU16::new(0),
+        }
+    }
+
+    /// Construct a Client builder for a sub-session whose application ID is
+    /// offset from this one's.  The addition wraps around once the ID no
+    /// longer fits in the bits left by the correlation token, which is
+    /// `u16::MAX` without one.
+    ///
+    /// The derived client uses the same number of token bits and continues
+    /// counting from this client's next token.
+    #[inline]
+    pub fn derive(&self, offset: u16) -> Client {
+        Client {
+            app_id: self.app_id.wrapping_add(offset) & (u16::MAX >> self.token_bits),
+            token_bits: self.token_bits,
+            token: AtomicU16::new(self.token.load(Ordering::Relaxed)),
+        }
     }
 
     /// Build a basic CQC request.
-    fn build(&self, msg_type: MsgType, req_cmd: Option<ReqCmd>) -> Request {
+    fn build(&self, msg_type: MsgType, body: ReqBody) -> Request {
         let cqc_hdr = CqcHdr {
             version: Version::V2,
-            msg_type: msg_type,
-            app_id: self.app_id,
-            length: match req_cmd {
-                Some(ref req) => req.len(),
-                None => 0,
-            },
+            msg_type,
+            app_id: self.next_app_id(),
+            length: body.len(),
         };
 
-        Request { cqc_hdr, req_cmd }
+        Request { cqc_hdr, body }
+    }
+
+    /// The application ID for the next request, including its correlation
+    /// token if one is in use.
+    fn next_app_id(&self) -> u16 {
+        if self.token_bits == 0 {
+            return self.app_id;
+        }
+
+        // The counter wraps at 2^16 which is a multiple of the token range,
+        // so masking it counts from 0 up to the largest token and back.
+        let shift = 16 - self.token_bits;
+        let token = self.token.fetch_add(1, Ordering::Relaxed) & (u16::MAX >> shift);
+
+        (token << shift) | self.app_id
+    }
+
+    /// Encode a request in the big-endian wire format.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn encode(&self, request: Request) -> Vec<u8> {
+        Encoder::new().into_vec(&request)
     }
 
     /// Build a liveness check request.
     #[inline]
     pub fn hello(&self) -> Request {
-        self.build(MsgType::Tp(Tp::Hello), None)
+        self.build(MsgType::Tp(Tp::Hello), ReqBody::None)
     }
 
     /// Build a qubit creation time query.
     #[inline]
     pub fn get_time(&self, qubit_id: u16) -> Request {
         let req_cmd = self.build_req_cmd(qubit_id, Cmd::I, CmdOpt::empty(), XtraHdr::None);
-        self.build(MsgType::Tp(Tp::GetTime), Some(req_cmd))
+        self.build(MsgType::Tp(Tp::GetTime), ReqBody::Cmd(vec![req_cmd]))
+    }
+
+    /// Build a factory request that executes `command` `num_iter` times.
+    #[inline]
+    pub fn factory(&self, num_iter: u8, options: FactoryOpt, command: ReqCmd) -> Request {
+        let factory_req = FactoryReq {
+            factory_hdr: FactoryHdr { num_iter, options },
+            req_cmd: command,
+        };
+        self.build(MsgType::Tp(Tp::Factory), ReqBody::Factory(factory_req))
     }
 
     /// Build a command request.
     fn command(&self, req_cmd: ReqCmd) -> Request {
-        self.build(MsgType::Tp(Tp::Command), Some(req_cmd))
+        self.build(MsgType::Tp(Tp::Command), ReqBody::Cmd(vec![req_cmd]))
+    }
+
+    /// Build a single command request that executes `commands` in one round
+    /// trip.  The ACTION option is set on every command but the last to chain
+    /// it to the next one.
+    ///
+    /// Returns an error if `commands` is empty, as the backend rejects a
+    /// command request without a command, or if the commands are together
+    /// longer than the CQC Header length field can represent.
+    pub fn sequence(&self, mut commands: Vec<ReqCmd>) -> Result<Request, BuildError> {
+        if commands.is_empty() {
+            return Err(BuildError::EmptySequence);
+        }
+        body_len(commands.iter().map(ReqCmd::len))?;
+
+        let last = commands.len() - 1;
+        for req_cmd in &mut commands[..last] {
+            req_cmd.cmd_hdr.options.set_action();
+        }
+        Ok(self.build(MsgType::Tp(Tp::Command), ReqBody::Cmd(commands)))
+    }
+
+    /// Start building a Mix program which combines several headers in one
+    /// request.
+    #[inline]
+    pub fn mix(&self) -> MixBuilder<'_> {
+        MixBuilder {
+            client: self,
+            segs: Vec::new(),
+        }
+    }
+
+    /// Build a conditional request that executes `then` only if the values
+    /// stored under the two reference IDs compare as specified by `operator`.
+    #[inline]
+    pub fn if_ref_cmp_ref(
+        &self,
+        left_ref: u32,
+        operator: CmpType,
+        right_ref: u32,
+        then: ReqCmd,
+    ) -> Request {
+        let if_hdr = IfHdr {
+            left_op: left_ref,
+            operator,
+            right_op_t: OpType::RefId,
+            right_op: right_ref,
+            length: then.len(),
+        };
+        let if_req = IfReq {
+            if_hdr,
+            req_cmd: then,
+        };
+        self.build(MsgType::Tp(Tp::If), ReqBody::If(if_req))
+    }
+
+    /// Build a command request for an instruction chosen at runtime.  The
+    /// caller is responsible for supplying the Xtra Header the instruction
+    /// requires.
+    #[inline]
+    pub fn cmd(&self, qubit_id: u16, instr: Cmd, options: CmdOpt, xtra_hdr: XtraHdr) -> Request {
+        self.command(self.build_req_cmd(qubit_id, instr, options, xtra_hdr))
     }
 
     /// Build an identity operation command request.
@@ -77,11 +281,66 @@ impl Client {
     pub fn cmd_new(&self, qubit_id: u16, options: CmdOpt) -> Request {
         self.command(self.build_req_cmd(qubit_id, Cmd::New, options, XtraHdr::None))
     }
+    /// Build and encode a qubit creation command request.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn cmd_new_bytes(&self, qubit_id: u16, options: CmdOpt) -> Vec<u8> {
+        self.encode(self.cmd_new(qubit_id, options))
+    }
     /// Build a measurement command request.
     #[inline]
     pub fn cmd_measure(&self, qubit_id: u16, options: CmdOpt) -> Request {
         self.command(self.build_req_cmd(qubit_id, Cmd::Measure, options, XtraHdr::None))
     }
+    /// Build a measurement request whose outcome is stored under `ref_id`.
+    ///
+    /// The Assign Header is only read in Mix programs so the request is a
+    /// Mix program with a single command segment.  Use `MixBuilder` to
+    /// combine the measurement with conditional commands.
+    #[inline]
+    pub fn cmd_measure_assign(&self, qubit_id: u16, options: CmdOpt, ref_id: u32) -> Request {
+        let xtra_hdr = XtraHdr::Assign(AssignHdr { ref_id });
+        self.mix().push_command(self.build_req_cmd(qubit_id, Cmd::Measure, options, xtra_hdr)).build()
+    }
+    /// Build a single command request that measures every qubit in
+    /// `qubit_ids` in order.  The returned collector gathers the outcomes
+    /// from the backend's MeasOut responses.
+    pub fn measure_all(&self, qubit_ids: &[u16], options: CmdOpt) -> (Request, MeasurementCollector) {
+        let cmds = qubit_ids
+            .iter()
+            .map(|&qubit_id| self.build_req_cmd(qubit_id, Cmd::Measure, options, XtraHdr::None))
+            .collect();
+        let request = self.build(MsgType::Tp(Tp::Command), ReqBody::Cmd(cmds));
+
+        (request, MeasurementCollector::new(qubit_ids.len()))
+    }
+    /// Build a single command request that prepares the qubits in
+    /// `qubit_ids` in a GHZ state.
+    ///
+    /// For qubits `q0, q1, ..., qn` the program is:
+    ///
+    /// 1. `New` on every qubit in order.
+    /// 2. `H` on `q0`.
+    /// 3. `CNOT` with control `q(i-1)` and target `qi` for `i` from 1 to `n`.
+    ///
+    /// Every command carries `options`.
+    pub fn prepare_ghz(&self, qubit_ids: &[u16], options: CmdOpt) -> Request {
+        let mut cmds: Vec<ReqCmd> = qubit_ids
+            .iter()
+            .map(|&qubit_id| self.build_req_cmd(qubit_id, Cmd::New, options, XtraHdr::None))
+            .collect();
+
+        if let Some(&first) = qubit_ids.first() {
+            cmds.push(self.build_req_cmd(first, Cmd::H, options, XtraHdr::None));
+        }
+
+        for pair in qubit_ids.windows(2) {
+            let xtra_hdr = self.xtra_target_qubit(pair[1]);
+            cmds.push(self.build_req_cmd(pair[0], Cmd::Cnot, options, xtra_hdr));
+        }
+
+        self.build(MsgType::Tp(Tp::Command), ReqBody::Cmd(cmds))
+    }
     /// Build an in-place measurement command request.
     #[inline]
     pub fn cmd_measure_inplace(&self, qubit_id: u16, options: CmdOpt) -> Request {
@@ -92,6 +351,17 @@ impl Client {
     pub fn cmd_reset(&self, qubit_id: u16, options: CmdOpt) -> Request {
         self.command(self.build_req_cmd(qubit_id, Cmd::Reset, options, XtraHdr::None))
     }
+    /// Build a qubit allocation command request.  The number of qubits to
+    /// allocate is carried in the qubit ID field.
+    #[inline]
+    pub fn cmd_allocate(&self, num_qubits: u16, options: CmdOpt) -> Request {
+        self.command(self.build_req_cmd(num_qubits, Cmd::Allocate, options, XtraHdr::None))
+    }
+    /// Build a qubit release command request.
+    #[inline]
+    pub fn cmd_release(&self, qubit_id: u16, options: CmdOpt) -> Request {
+        self.command(self.build_req_cmd(qubit_id, Cmd::Release, options, XtraHdr::None))
+    }
     /// Build a send command request.  This command has to identify the remote node to send to.
     #[inline]
     pub fn cmd_send(&self, qubit_id: u16, options: CmdOpt, remote_id: RemoteId) -> Request {
@@ -115,6 +385,16 @@ impl Client {
         self.command(self.build_req_cmd(qubit_id, Cmd::EprRecv, options, XtraHdr::None))
     }
 
+    /// Build requests addressed to a single remote node without repeating
+    /// its `RemoteId`.
+    #[inline]
+    pub fn targeting(&self, remote_id: RemoteId) -> RemoteClient<'_> {
+        RemoteClient {
+            client: self,
+            remote_id,
+        }
+    }
+
     /// Build a Pauli X command request.
     #[inline]
     pub fn cmd_x(&self, qubit_id: u16, options: CmdOpt) -> Request {
@@ -130,29 +410,40 @@ impl Client {
     pub fn cmd_y(&self, qubit_id: u16, options: CmdOpt) -> Request {
         self.command(self.build_req_cmd(qubit_id, Cmd::Y, options, XtraHdr::None))
     }
+    /// Build a Pauli operation command request.
+    #[inline]
+    pub fn cmd_pauli(&self, qubit_id: u16, pauli: Pauli, options: CmdOpt) -> Request {
+        self.command(self.build_req_cmd(qubit_id, Cmd::from(pauli), options, XtraHdr::None))
+    }
     /// Build a T Gate command request.
     #[inline]
     pub fn cmd_t(&self, qubit_id: u16, options: CmdOpt) -> Request {
         self.command(self.build_req_cmd(qubit_id, Cmd::T, options, XtraHdr::None))
     }
-    /// Build an X rotation command request.  Rotation is specified in steps of pi/256 increments.
+    /// Build an X rotation command request.  Rotation is specified in steps of 2pi/256 increments.
     #[inline]
     pub fn cmd_rot_x(&self, qubit_id: u16, options: CmdOpt, steps: u8) -> Request {
         let xtra_hdr = self.xtra_rotation_angle(steps);
         self.command(self.build_req_cmd(qubit_id, Cmd::RotX, options, xtra_hdr))
     }
-    /// Build a Y rotation command request.  Rotation is specified in steps of pi/256 increments.
+    /// Build a Y rotation command request.  Rotation is specified in steps of 2pi/256 increments.
     #[inline]
     pub fn cmd_rot_y(&self, qubit_id: u16, options: CmdOpt, steps: u8) -> Request {
         let xtra_hdr = self.xtra_rotation_angle(steps);
         self.command(self.build_req_cmd(qubit_id, Cmd::RotY, options, xtra_hdr))
     }
-    /// Build a Z rotation command request.  Rotation is specified in steps of pi/256 increments.
+    /// Build a Z rotation command request.  Rotation is specified in steps of 2pi/256 increments.
     #[inline]
     pub fn cmd_rot_z(&self, qubit_id: u16, options: CmdOpt, steps: u8) -> Request {
         let xtra_hdr = self.xtra_rotation_angle(steps);
         self.command(self.build_req_cmd(qubit_id, Cmd::RotZ, options, xtra_hdr))
     }
+    /// Build a rotation command request around an axis chosen at runtime.  Rotation is specified in steps of 2pi/256 increments.
+    #[inline]
+    pub fn cmd_rot(&self, qubit_id: u16, axis: Axis, options: CmdOpt, steps: u8) -> Request {
+        let xtra_hdr = self.xtra_rotation_angle(steps);
+        self.command(self.build_req_cmd(qubit_id, Cmd::from(axis), options, xtra_hdr))
+    }
     /// Build a Hadamard Gate command request.
     #[inline]
     pub fn cmd_h(&self, qubit_id: u16, options: CmdOpt) -> Request {
@@ -163,6 +454,27 @@ impl Client {
     pub fn cmd_k(&self, qubit_id: u16, options: CmdOpt) -> Request {
         self.command(self.build_req_cmd(qubit_id, Cmd::K, options, XtraHdr::None))
     }
+    /// Build an inverse K Gate command request.  K is its own inverse so this
+    /// is a K Gate, taking the Y eigenbasis back to the computational basis.
+    #[inline]
+    pub fn cmd_k_inverse(&self, qubit_id: u16, options: CmdOpt) -> Request {
+        self.cmd_k(qubit_id, options)
+    }
+    /// Build the adjoint of a T Gate as a Mix program of rotations.
+    ///
+    /// CQC has no opcode for T-dagger so it is decomposed as a single Z
+    /// rotation by 224 steps, i.e. by 7pi/4 = -pi/4, which equals T-dagger up
+    /// to a global phase.
+    pub fn cmd_t_dagger(&self, qubit_id: u16, options: CmdOpt) -> Request {
+        let rot_z = self.build_req_cmd(qubit_id, Cmd::RotZ, options, self.xtra_rotation_angle(224));
+        self.mix().push_command(rot_z).build()
+    }
+    /// Build the adjoint of a K Gate.  K is its own adjoint so this is a K
+    /// Gate, taking the Y eigenbasis back to the computational basis.
+    #[inline]
+    pub fn cmd_k_dagger(&self, qubit_id: u16, options: CmdOpt) -> Request {
+        self.cmd_k(qubit_id, options)
+    }
 
     /// Build a CNOT command request.  Requires a target qubit.
     #[inline]
@@ -203,7 +515,7 @@ impl Client {
         })
     }
 
-    /// Build an Xtra Header that specifies a rotation angle in pi/256 increments.
+    /// Build an Xtra Header that specifies a rotation angle in 2pi/256 increments.
     fn xtra_rotation_angle(&self, step: u8) -> XtraHdr {
         XtraHdr::Rot(RotHdr { step })
     }
@@ -214,6 +526,335 @@ impl Client {
     }
 }
 
+/// The Program builder accumulates commands into a single command request
+/// for a particular application ID.
+pub struct ProgramBuilder {
+    client: Client,
+    cmds: Vec<ReqCmd>,
+    auto_allocate: bool,
+    created: BTreeSet<u16>,
+}
+
+impl ProgramBuilder {
+    /// Construct a Program builder.
+    #[inline]
+    pub fn new(app_id: u16) -> Self {
+        ProgramBuilder {
+            client: Client::new(app_id),
+            cmds: Vec::new(),
+            auto_allocate: false,
+            created: BTreeSet::new(),
+        }
+    }
+
+    /// When enabled, a New command is inserted before the first command that
+    /// uses a qubit ID which no earlier command has created.  Qubits are
+    /// created by New, Recv, Epr, and EprRecv and are forgotten on Release.
+    #[inline]
+    pub fn auto_allocate(mut self, enable: bool) -> Self {
+        self.auto_allocate = enable;
+        self
+    }
+
+    /// Append a command to the program.
+    pub fn push(mut self, req_cmd: ReqCmd) -> Self {
+        let qubit_id = req_cmd.cmd_hdr.qubit_id;
+        match req_cmd.cmd_hdr.instr {
+            Cmd::New | Cmd::Recv | Cmd::Epr | Cmd::EprRecv => {
+                self.created.insert(qubit_id);
+            }
+            Cmd::Allocate => (),
+            Cmd::Release => {
+                self.allocate(qubit_id);
+                self.created.remove(&qubit_id);
+            }
+            _ => {
+                self.allocate(qubit_id);
+                if let XtraHdr::Qubit(ref hdr) = req_cmd.xtra_hdr {
+                    self.allocate(hdr.qubit_id);
+                }
+            }
+        }
+
+        self.cmds.push(req_cmd);
+        self
+    }
+
+    /// Build the command request containing every command in the program.
+    ///
+    /// Panics if the program is too long to be encoded.  Use `try_build` to
+    /// handle this case instead.
+    pub fn build(self) -> Request {
+        match self.try_build() {
+            Ok(request) => request,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Build the command request containing every command in the program.
+    ///
+    /// Returns an error if the commands are together longer than the CQC
+    /// Header length field can represent.
+    pub fn try_build(self) -> Result<Request, BuildError> {
+        body_len(self.cmds.iter().map(ReqCmd::len))?;
+        Ok(self.client.build(MsgType::Tp(Tp::Command), ReqBody::Cmd(self.cmds)))
+    }
+
+    /// Insert a New command for the qubit if auto allocation is enabled and
+    /// the qubit has not been created yet.
+    fn allocate(&mut self, qubit_id: u16) {
+        if self.auto_allocate && self.created.insert(qubit_id) {
+            let req_cmd = self.client.build_req_cmd(qubit_id, Cmd::New, CmdOpt::empty(), XtraHdr::None);
+            self.cmds.push(req_cmd);
+        }
+    }
+}
+
+/// The Mix builder constructs a Mix program in which every segment is
+/// announced by a Type Header.  It is created with `Client::mix`.
+pub struct MixBuilder<'a> {
+    client: &'a Client,
+    segs: Vec<MixSeg>,
+}
+
+impl<'a> MixBuilder<'a> {
+    /// Append a command segment.
+    pub fn push_command(mut self, req_cmd: ReqCmd) -> Self {
+        self.push(Tp::Command, ReqBody::Cmd(vec![req_cmd]));
+        self
+    }
+
+    /// Append a conditional segment that executes `then` only if the
+    /// condition in `if_hdr` holds.  The length in `if_hdr` is overwritten
+    /// with the length of `then`.
+    pub fn push_if(mut self, mut if_hdr: IfHdr, then: ReqCmd) -> Self {
+        if_hdr.length = then.len();
+        let if_req = IfReq {
+            if_hdr,
+            req_cmd: then,
+        };
+        self.push(Tp::If, ReqBody::If(if_req));
+        self
+    }
+
+    /// Build the Mix request containing every segment.
+    ///
+    /// Panics if the program is too long to be encoded or if a measurement
+    /// does not carry an Assign Header.  Use `try_build` to handle these
+    /// cases instead.
+    pub fn build(self) -> Request {
+        match self.try_build() {
+            Ok(request) => request,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Build the Mix request containing every segment.
+    ///
+    /// Returns an error if the segments are together longer than the CQC
+    /// Header length field can represent.  Every measurement in a Mix
+    /// program must carry an Assign Header, as built by
+    /// `Client::cmd_measure_assign`, so an error is also returned if one
+    /// does not.
+    pub fn try_build(self) -> Result<Request, BuildError> {
+        for seg in &self.segs {
+            for req_cmd in seg.body.cmds() {
+                if req_cmd.lacks_assign_hdr() {
+                    let instr = req_cmd.cmd_hdr.instr;
+                    return Err(BuildError::MissingAssignHdr(instr));
+                }
+            }
+        }
+        body_len(self.segs.iter().map(MixSeg::len))?;
+        Ok(self.client.build(MsgType::Tp(Tp::Mix), ReqBody::Mix(self.segs)))
+    }
+
+    /// Append a segment announced by a Type Header of the given type.
+    fn push(&mut self, hdr_type: Tp, body: ReqBody) {
+        let type_hdr = TypeHdr {
+            hdr_type,
+            length: body.len(),
+        };
+        self.segs.push(MixSeg { type_hdr, body });
+    }
+}
+
+/// Marker for an EPR flow that has not yet requested an EPR pair.
+pub struct Idle;
+
+/// Marker for an EPR flow that has requested an EPR pair.
+pub struct Initiated;
+
+/// The EPR flow builds the two steps of the EPR protocol in order.  The
+/// receive request is only available once the creation request has been
+/// built.
+///
+/// ```
+/// use cqc::builder::{Client, EprFlow, RemoteId};
+/// use cqc::hdr::CmdOpt;
+///
+/// let client = Client::new(10);
+/// let remote_id = RemoteId {
+///     remote_app_id: 10,
+///     remote_port: 8804,
+///     remote_node: 0x7f000001,
+/// };
+///
+/// let (epr, flow) = EprFlow::new(&client, 1, CmdOpt::empty())
+///     .initiate(remote_id);
+/// let recv = flow.recv();
+/// # let _ = (epr, recv);
+/// ```
+///
+/// Skipping the creation step does not compile.
+///
+/// ```compile_fail
+/// use cqc::builder::{Client, EprFlow};
+/// use cqc::hdr::CmdOpt;
+///
+/// let client = Client::new(10);
+/// let recv = EprFlow::new(&client, 1, CmdOpt::empty()).recv();
+/// ```
+pub struct EprFlow<'a, S> {
+    client: &'a Client,
+    qubit_id: u16,
+    options: CmdOpt,
+    state: PhantomData<S>,
+}
+
+impl<'a> EprFlow<'a, Idle> {
+    /// Start an EPR flow for the given qubit.
+    #[inline]
+    pub fn new(client: &'a Client, qubit_id: u16, options: CmdOpt) -> Self {
+        EprFlow {
+            client,
+            qubit_id,
+            options,
+            state: PhantomData,
+        }
+    }
+
+    /// Build the EPR creation request with `Client::cmd_epr`.
+    pub fn initiate(self, remote_id: RemoteId) -> (Request, EprFlow<'a, Initiated>) {
+        let request = self.client.cmd_epr(self.qubit_id, self.options, remote_id);
+        let flow = EprFlow {
+            client: self.client,
+            qubit_id: self.qubit_id,
+            options: self.options,
+            state: PhantomData,
+        };
+        (request, flow)
+    }
+}
+
+impl<'a> EprFlow<'a, Initiated> {
+    /// Build the EPR receive request with `Client::cmd_epr_recv`.
+    pub fn recv(self) -> Request {
+        self.client.cmd_epr_recv(self.qubit_id, self.options)
+    }
+}
+
+/// # Remote Client
+///
+/// A Client bound to a remote node.  Requests that address a remote node are
+/// built with the stored `RemoteId`.
+///
+/// ```
+/// use cqc::builder::{Client, RemoteId};
+/// use cqc::hdr::CmdOpt;
+///
+/// let client = Client::new(10);
+/// let remote = client.targeting(RemoteId {
+///     remote_app_id: 10,
+///     remote_port: 8804,
+///     remote_node: 0x7f000001,
+/// });
+///
+/// let requests: Vec<_> = (0..4)
+///     .map(|qubit_id| remote.send(qubit_id, CmdOpt::notify()))
+///     .collect();
+/// # let _ = requests;
+/// ```
+pub struct RemoteClient<'a> {
+    client: &'a Client,
+    remote_id: RemoteId,
+}
+
+impl<'a> RemoteClient<'a> {
+    /// The remote node addressed by this client.
+    #[inline]
+    pub fn remote_id(&self) -> RemoteId {
+        self.remote_id
+    }
+
+    /// Build a send command request with `Client::cmd_send`.
+    #[inline]
+    pub fn send(&self, qubit_id: u16, options: CmdOpt) -> Request {
+        self.client.cmd_send(qubit_id, options, self.remote_id)
+    }
+
+    /// Build an EPR creation command request with `Client::cmd_epr`.
+    #[inline]
+    pub fn epr(&self, qubit_id: u16, options: CmdOpt) -> Request {
+        self.client.cmd_epr(qubit_id, options, self.remote_id)
+    }
+}
+
+/// Sum the lengths of the parts of a packet body.  Returns an error if the
+/// total does not fit in the CQC Header length field.
+fn body_len<I>(lens: I) -> Result<u32, BuildError>
+where
+    I: IntoIterator<Item = u32>,
+{
+    let length: u64 = lens.into_iter().map(u64::from).sum();
+    if length > u64::from(u32::MAX) {
+        return Err(BuildError::PacketTooLong { length });
+    }
+    Ok(length as u32)
+}
+
+/// Accumulates the outcomes of the measurements requested by
+/// `Client::measure_all` in the order in which they are received.
+#[derive(Debug)]
+pub struct MeasurementCollector {
+    expected: usize,
+    outcomes: Vec<MeasOut>,
+}
+
+impl MeasurementCollector {
+    /// Construct a collector expecting `expected` measurement outcomes.
+    #[inline]
+    pub fn new(expected: usize) -> Self {
+        MeasurementCollector {
+            expected,
+            outcomes: Vec::with_capacity(expected),
+        }
+    }
+
+    /// Record a response.  Responses other than MeasOut, such as a Done
+    /// notification, are ignored.  Returns all the outcomes once the last
+    /// expected one has been received.
+    pub fn push(&mut self, response: &Response) -> Option<Vec<MeasOut>> {
+        if let RspInfo::MeasOut(ref hdr) = response.notify {
+            if !self.is_complete() {
+                self.outcomes.push(hdr.meas_out);
+            }
+        }
+
+        if self.is_complete() {
+            Some(self.outcomes.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Check whether all the expected outcomes have been received.
+    #[inline]
+    pub fn is_complete(&self) -> bool {
+        self.outcomes.len() == self.expected
+    }
+}
+
 /// The Server builder constructs responses for a particular application ID.
 pub struct Server {
     app_id: u16,
@@ -230,7 +871,7 @@ impl Server {
     fn build(&self, msg_type: MsgType, notify: RspInfo) -> Response {
         let cqc_hdr = CqcHdr {
             version: Version::V2,
-            msg_type: msg_type,
+            msg_type,
             app_id: self.app_id,
             length: notify.len(),
         };
@@ -238,6 +879,11 @@ impl Server {
         Response { cqc_hdr, notify }
     }
 
+    /// Build a Hello message acknowledging a liveness check.
+    #[inline]
+    pub fn hello_ack(&self) -> Response {
+        self.build(MsgType::Tp(Tp::Hello), RspInfo::None)
+    }
     /// Build an Expire notification.
     #[inline]
     pub fn expire(&self, qubit_id: u16) -> Response {
@@ -249,6 +895,11 @@ impl Server {
     pub fn done(&self) -> Response {
         self.build(MsgType::Tp(Tp::Done), RspInfo::None)
     }
+    /// Build the grouped result of a factory, one response per iteration.
+    #[inline]
+    pub fn factory(&self, responses: Vec<Response>) -> Response {
+        self.build(MsgType::Tp(Tp::Factory), RspInfo::Factory(responses))
+    }
     /// Build a Recv message for a received qubit.
     #[inline]
     pub fn recv(&self, qubit_id: u16) -> Response {
@@ -261,6 +912,33 @@ impl Server {
         let notify = self.rsp_info_epr(qubit_id, ent_info);
         self.build(MsgType::Tp(Tp::EprOk), notify)
     }
+    /// Build an EPR message for a pair shared between nodes `a` and `b`.  The
+    /// creation time and the time of goodness are set to the current time and
+    /// both application IDs are set to the one of this builder.
+    #[cfg(feature = "std")]
+    pub fn epr_ok_between(
+        &self,
+        qubit_id: u16,
+        a: SocketAddrV4,
+        b: SocketAddrV4,
+        ent_id: u32,
+        goodness: u16,
+        df: Directionality,
+    ) -> Response {
+        let now = SystemTime::now();
+        let record = EntanglementRecord {
+            node_a: a,
+            app_id_a: self.app_id,
+            node_b: b,
+            app_id_b: self.app_id,
+            id_ab: ent_id,
+            timestamp: now,
+            tog: now,
+            goodness,
+            directionality: Some(df),
+        };
+        self.epr_ok(qubit_id, EntInfoHdr::from(record))
+    }
     /// Build a MeasOut message for a measurement outcome.
     #[inline]
     pub fn meas_out(&self, meas_out: MeasOut) -> Response {
@@ -273,6 +951,15 @@ impl Server {
         let notify = self.rsp_info_time_info(datetime);
         self.build(MsgType::Tp(Tp::InfTime), notify)
     }
+    /// Build the reply to a `Client::get_time` request.
+    ///
+    /// The reply carries the creation time of the qubit in a Time Info
+    /// Header.  SimulaQron answers GetTime with an InfTime message, not a
+    /// GetTime message, so this is the same response as `inf_time`.
+    #[inline]
+    pub fn get_time_response(&self, datetime: u64) -> Response {
+        self.inf_time(datetime)
+    }
     /// Build a NewOk message for a new qubit.
     #[inline]
     pub fn new_ok(&self, qubit_id: u16) -> Response {
@@ -280,6 +967,52 @@ impl Server {
         self.build(MsgType::Tp(Tp::NewOk), notify)
     }
 
+    /// Build an error response.
+    #[inline]
+    pub fn error(&self, err: Err) -> Response {
+        self.build(MsgType::Err(err), RspInfo::None)
+    }
+    /// Build a General error response.
+    #[inline]
+    pub fn err_general(&self) -> Response {
+        self.error(Err::General)
+    }
+    /// Build a NoQubit error response.
+    #[inline]
+    pub fn err_no_qubit(&self) -> Response {
+        self.error(Err::NoQubit)
+    }
+    /// Build an Unsupp error response.
+    #[inline]
+    pub fn err_unsupp(&self) -> Response {
+        self.error(Err::Unsupp)
+    }
+    /// Build an Unsupp error response naming the unsupported command.
+    ///
+    /// This is a non-standard extension of CQC.  The instruction code of the
+    /// command is sent as a single byte body after the CQC Header.  Clients
+    /// that do not know the extension keep the byte as a raw body.  Use
+    /// `Response::unsupported_command` to recover the command.
+    #[inline]
+    pub fn unsupported(&self, cmd: Cmd) -> Response {
+        self.build(MsgType::Err(Err::Unsupp), RspInfo::Raw(vec![cmd as u8]))
+    }
+    /// Build a Timeout error response.
+    #[inline]
+    pub fn err_timeout(&self) -> Response {
+        self.error(Err::Timeout)
+    }
+    /// Build an InUse error response.
+    #[inline]
+    pub fn err_in_use(&self) -> Response {
+        self.error(Err::InUse)
+    }
+    /// Build an Unknown error response.
+    #[inline]
+    pub fn err_unknown(&self) -> Response {
+        self.error(Err::Unknown)
+    }
+
     /// Build an RspInfo message block for a qubit.
     fn rsp_info_qubit(&self, qubit_id: u16) -> RspInfo {
         RspInfo::Qubit(QubitHdr { qubit_id })
@@ -305,3 +1038,20 @@ impl Server {
         RspInfo::Time(TimeInfoHdr { datetime })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn body_len_overflow() {
+        let half = u32::MAX / 2 + 1;
+        assert_eq!(body_len(vec![half]), Ok(half));
+        assert_eq!(
+            body_len(vec![half, half]),
+            Err(BuildError::PacketTooLong {
+                length: 2 * u64::from(half)
+            })
+        );
+    }
+}
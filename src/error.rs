@@ -0,0 +1,280 @@
+//! # Errors
+//!
+//! Error types returned by the packet validation, encoding, and decoding
+//! utilities in this crate.
+
+#[cfg(feature = "std")]
+extern crate bincode;
+
+use hdr::{Cmd, MsgType};
+#[cfg(feature = "std")]
+use std::error::Error;
+use std::fmt;
+
+/// # Decode Error
+///
+/// Returned when a buffer cannot be decoded into a CQC packet.
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The underlying deserialisation failed.
+    #[cfg(feature = "std")]
+    Bincode(bincode::Error),
+    /// The CQC Header carries a version this crate does not support.
+    UnsupportedVersion(u8),
+    /// The CQC Header carries an unknown message type.
+    InvalidMessageType(u8),
+    /// The buffer ends before the packet announced by the CQC Header.
+    Truncated { needed: usize, got: usize },
+    /// A Command, Factory, or GetTime message has no commands.  The protocol
+    /// has no notion of an empty command list.
+    EmptyCommandBody(MsgType),
+    /// A Factory message has a Factory Header but no command to repeat.
+    EmptyFactoryBody,
+    /// The buffer continues past the end of the packet.
+    TrailingBytes { packet_len: usize, got: usize },
+    /// A Type Header in a Mix program announces more bytes than remain.
+    TypeHeaderOverrun { declared: u32, remaining: u32 },
+    /// A message type only sent by the backend was decoded as a request.
+    NotRequest(MsgType),
+    /// A message type only sent by the client was decoded as a response.
+    NotResponse(MsgType),
+    /// A response of a different type than the one expected.
+    UnexpectedResponse { expected: MsgType, actual: MsgType },
+    /// An entanglement ID for a node pair is lower than one already seen.
+    NonMonotonicEntId {
+        node_a: u32,
+        node_b: u32,
+        last: u32,
+        id: u32,
+    },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            #[cfg(feature = "std")]
+            DecodeError::Bincode(ref err) => write!(f, "{}", err),
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "Invalid CQC version: {}", version)
+            }
+            DecodeError::InvalidMessageType(msg_type) => {
+                write!(f, "Invalid CQC message type: {}", msg_type)
+            }
+            DecodeError::Truncated { needed, got } => write!(
+                f,
+                "Packet is truncated: {} bytes needed, {} available",
+                needed, got
+            ),
+            DecodeError::EmptyCommandBody(msg_type) => {
+                write!(f, "{} message with an empty command body", msg_type)
+            }
+            DecodeError::EmptyFactoryBody => {
+                write!(f, "Factory message without a command to repeat")
+            }
+            DecodeError::TrailingBytes { packet_len, got } => write!(
+                f,
+                "Packet is {} bytes long, but the buffer holds {} bytes",
+                packet_len, got
+            ),
+            DecodeError::TypeHeaderOverrun {
+                declared,
+                remaining,
+            } => write!(
+                f,
+                "Type Header announces {} bytes, but only {} remain",
+                declared, remaining
+            ),
+            DecodeError::NotRequest(msg_type) => {
+                write!(f, "{} message is a response, not a request", msg_type)
+            }
+            DecodeError::NotResponse(msg_type) => {
+                write!(f, "{} message is a request, not a response", msg_type)
+            }
+            DecodeError::UnexpectedResponse { expected, actual } => {
+                write!(f, "Expected a {} response, got {}", expected, actual)
+            }
+            DecodeError::NonMonotonicEntId {
+                node_a,
+                node_b,
+                last,
+                id,
+            } => write!(
+                f,
+                "Entanglement ID {} between nodes {:#010x} and {:#010x} \
+                 regresses from {}",
+                id, node_a, node_b, last
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for DecodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            DecodeError::Bincode(ref err) => Some(err),
+            DecodeError::UnsupportedVersion(_)
+            | DecodeError::InvalidMessageType(_)
+            | DecodeError::Truncated { .. }
+            | DecodeError::EmptyCommandBody(_)
+            | DecodeError::EmptyFactoryBody
+            | DecodeError::TrailingBytes { .. }
+            | DecodeError::TypeHeaderOverrun { .. }
+            | DecodeError::NotRequest(_)
+            | DecodeError::NotResponse(_)
+            | DecodeError::UnexpectedResponse { .. }
+            | DecodeError::NonMonotonicEntId { .. } => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<bincode::Error> for DecodeError {
+    fn from(err: bincode::Error) -> Self {
+        DecodeError::Bincode(err)
+    }
+}
+
+/// # Encode Error
+///
+/// Returned when a CQC packet cannot be encoded into the provided buffer.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum EncodeError {
+    /// The buffer is smaller than the encoded packet.
+    BufferTooSmall { needed: usize, got: usize },
+    /// The underlying serialisation failed.
+    Bincode(bincode::Error),
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EncodeError::BufferTooSmall { needed, got } => write!(
+                f,
+                "failed to write whole buffer: {} bytes needed, {} available",
+                needed, got
+            ),
+            EncodeError::Bincode(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for EncodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            EncodeError::BufferTooSmall { .. } => None,
+            EncodeError::Bincode(ref err) => Some(err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<bincode::Error> for EncodeError {
+    fn from(err: bincode::Error) -> Self {
+        EncodeError::Bincode(err)
+    }
+}
+
+/// # Build Error
+///
+/// Returned when the builder cannot represent the requested packet.
+#[derive(Debug, PartialEq)]
+pub enum BuildError {
+    /// The body is longer than the 32-bit CQC Header length field allows.
+    PacketTooLong { length: u64 },
+    /// A measurement in a Mix program does not carry an Assign Header.
+    MissingAssignHdr(Cmd),
+    /// A command sequence contains no commands.
+    EmptySequence,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BuildError::PacketTooLong { length } => write!(
+                f,
+                "Packet body of {} bytes does not fit in the length field",
+                length
+            ),
+            BuildError::MissingAssignHdr(instr) => write!(
+                f,
+                "{:?} in a Mix program does not carry an Assign Header",
+                instr
+            ),
+            BuildError::EmptySequence => {
+                write!(f, "A command sequence needs at least one command")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for BuildError {}
+
+/// # Validation Error
+///
+/// Returned when a packet is internally inconsistent and would be rejected by
+/// the CQC backend.
+#[derive(Debug, PartialEq)]
+pub enum ValidationError {
+    /// The extra header does not match the one required by the instruction.
+    XtraHdrMismatch(Cmd),
+    /// A two-qubit gate uses the same qubit as both control and target.
+    TargetIsControl(Cmd, u16),
+    /// The length in the CQC Header does not match the length of the body.
+    LengthMismatch { declared: u32, actual: u32 },
+    /// The body of a response is not the one carried by its message type.
+    NotifyMismatch(MsgType),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidationError::XtraHdrMismatch(instr) => {
+                write!(f, "Invalid extra header for instruction {:?}", instr)
+            }
+            ValidationError::TargetIsControl(instr, qubit_id) => write!(
+                f,
+                "Instruction {:?} uses qubit {} as both control and target",
+                instr, qubit_id
+            ),
+            ValidationError::LengthMismatch { declared, actual } => write!(
+                f,
+                "CQC Header announces {} bytes, but the body is {} bytes",
+                declared, actual
+            ),
+            ValidationError::NotifyMismatch(msg_type) => write!(
+                f,
+                "Invalid response body for message type {:?}",
+                msg_type
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for ValidationError {}
+
+/// # Invalid Value Error
+///
+/// Returned when an 8-bit value does not correspond to any variant of a CQC
+/// enum.
+#[derive(Debug, PartialEq)]
+pub struct InvalidValue {
+    /// The name of the field being converted.
+    pub name: &'static str,
+    /// The offending value.
+    pub value: u8,
+}
+
+impl fmt::Display for InvalidValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid {}: {}", self.name, self.value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for InvalidValue {}
@@ -0,0 +1,135 @@
+//! # Fuzzing Seeds
+//!
+//! Minimal valid packets for use as the initial corpus of a fuzzer.  Each seed
+//! carries the smallest body its message type accepts and refers to qubit 0.
+
+use builder::{Client, Server};
+use hdr::*;
+use {Encoder, MixSeg, ReqBody, ReqCmd, Request, XtraHdr};
+
+/// Application ID used in all seeds.
+const APP_ID: u16 = 0;
+
+/// Build the encoded bytes of a minimal valid packet of the given message
+/// type.  Message types sent by the client are encoded as requests and those
+/// sent by the backend are encoded as responses.
+pub fn seed_for(msg_type: Tp) -> Vec<u8> {
+    let client = Client::new(APP_ID);
+    let server = Server::new(APP_ID);
+    let encoder = Encoder::new();
+
+    match msg_type {
+        Tp::Hello => encoder.into_vec(&client.hello()),
+        Tp::Command => encoder.into_vec(&client.cmd_i(0, CmdOpt::empty())),
+        Tp::Factory => encoder.into_vec(&client.factory(
+            1,
+            FactoryOpt::empty(),
+            req_cmd_i(),
+        )),
+        Tp::Expire => encoder.into_vec(&server.expire(0)),
+        Tp::Done => encoder.into_vec(&server.done()),
+        Tp::Recv => encoder.into_vec(&server.recv(0)),
+        Tp::EprOk => encoder.into_vec(&server.epr_ok(0, ent_info())),
+        Tp::MeasOut => encoder.into_vec(&server.meas_out(MeasOut::Zero)),
+        Tp::GetTime => encoder.into_vec(&client.get_time(0)),
+        Tp::InfTime => encoder.into_vec(&server.inf_time(0)),
+        Tp::NewOk => encoder.into_vec(&server.new_ok(0)),
+        Tp::Mix => encoder.into_vec(&mix()),
+        Tp::If => encoder.into_vec(&client.if_ref_cmp_ref(
+            0,
+            CmpType::Eq,
+            0,
+            req_cmd_i(),
+        )),
+    }
+}
+
+/// An identity operation on qubit 0.
+fn req_cmd_i() -> ReqCmd {
+    ReqCmd {
+        cmd_hdr: CmdHdr {
+            qubit_id: 0,
+            instr: Cmd::I,
+            options: CmdOpt::empty(),
+        },
+        xtra_hdr: XtraHdr::None,
+    }
+}
+
+/// Entanglement information with every field zeroed.
+fn ent_info() -> EntInfoHdr {
+    EntInfoHdr {
+        node_a: 0,
+        port_a: 0,
+        app_id_a: 0,
+        node_b: 0,
+        port_b: 0,
+        app_id_b: 0,
+        id_ab: 0,
+        timestamp: 0,
+        tog: 0,
+        goodness: 0,
+        df: 0,
+        align: 0,
+    }
+}
+
+/// A Mix program with a single command segment.
+fn mix() -> Request {
+    let req_cmd = req_cmd_i();
+    let body = ReqBody::Mix(vec![MixSeg {
+        type_hdr: TypeHdr {
+            hdr_type: Tp::Command,
+            length: req_cmd.len(),
+        },
+        body: ReqBody::Cmd(vec![req_cmd]),
+    }]);
+
+    Request {
+        cqc_hdr: CqcHdr {
+            version: Version::V2,
+            msg_type: MsgType::Tp(Tp::Mix),
+            app_id: APP_ID,
+            length: body.len(),
+        },
+        body,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Decoder, Response};
+
+    #[test]
+    fn seeds_decode() {
+        let decoder = Decoder::new();
+
+        for &tp in &[
+            Tp::Hello,
+            Tp::Command,
+            Tp::Factory,
+            Tp::GetTime,
+            Tp::Mix,
+            Tp::If,
+        ] {
+            let seed = seed_for(tp);
+            let request: Request = decoder.decode(&seed[..]).unwrap();
+            assert_eq!(request.cqc_hdr.msg_type, MsgType::Tp(tp));
+        }
+
+        for &tp in &[
+            Tp::Expire,
+            Tp::Done,
+            Tp::Recv,
+            Tp::EprOk,
+            Tp::MeasOut,
+            Tp::InfTime,
+            Tp::NewOk,
+        ] {
+            let seed = seed_for(tp);
+            let response: Response = decoder.decode(&seed[..]).unwrap();
+            assert_eq!(response.cqc_hdr.msg_type, MsgType::Tp(tp));
+        }
+    }
+}
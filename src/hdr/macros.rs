@@ -5,10 +5,11 @@ macro_rules! def_len {
     ($hdr_name: ident, $value: expr) => {
         impl $hdr_name {
             #[inline]
-            pub fn hdr_len() -> u32 {
+            pub const fn hdr_len() -> u32 {
                 $value
             }
             #[inline]
+            #[allow(clippy::len_without_is_empty)]
             pub fn len(&self) -> u32 {
                 $value
             }
@@ -73,6 +74,19 @@ macro_rules! serde_option_u8 {
             {
                 Ok($opt_name::from_bits_truncate(value))
             }
+
+            // Self-describing formats, such as JSON, do not preserve the
+            // integer width.
+            #[inline]
+            fn visit_u64<E>(self, value: u64) -> Result<$opt_name, E>
+            where
+                E: de::Error,
+            {
+                match u8::try_from(value) {
+                    Ok(value) => self.visit_u8(value),
+                    Err(_) => Err(E::invalid_value(Unexpected::Unsigned(value), &self)),
+                }
+            }
         }
 
         impl<'de> Deserialize<'de> for $opt_name {
@@ -105,10 +119,14 @@ macro_rules! serialize_enum_u8 {
 }
 
 // ----------------------------------------------------------------------------
-// Implement the Deserialize trait on a u8 enum.
+// Implement the Deserialize trait on a u8 enum.  The value is converted with
+// `get` unless another conversion function is given.
 // ----------------------------------------------------------------------------
 macro_rules! deserialize_enum_u8 {
     ($enum_name: ident, $visitor_name: ident, $str_name: expr) => {
+        deserialize_enum_u8!($enum_name, $visitor_name, $str_name, $enum_name::get);
+    };
+    ($enum_name: ident, $visitor_name: ident, $str_name: expr, $get: path) => {
         struct $visitor_name;
 
         impl<'de> Visitor<'de> for $visitor_name {
@@ -124,7 +142,7 @@ macro_rules! deserialize_enum_u8 {
             where
                 E: de::Error,
             {
-                let instr = match $enum_name::get(value) {
+                let instr = match $get(value) {
                     Some(x) => x,
                     None => {
                         return Err(E::custom(
@@ -135,6 +153,19 @@ macro_rules! deserialize_enum_u8 {
 
                 Ok(instr)
             }
+
+            // Self-describing formats, such as JSON, do not preserve the
+            // integer width.
+            #[inline]
+            fn visit_u64<E>(self, value: u64) -> Result<$enum_name, E>
+            where
+                E: de::Error,
+            {
+                match u8::try_from(value) {
+                    Ok(value) => self.visit_u8(value),
+                    Err(_) => Err(E::invalid_value(Unexpected::Unsigned(value), &self)),
+                }
+            }
         }
 
         impl<'de> Deserialize<'de> for $enum_name {
@@ -149,6 +180,25 @@ macro_rules! deserialize_enum_u8 {
     }
 }
 
+// ----------------------------------------------------------------------------
+// Implement the TryFrom<u8> trait on a u8 enum.
+// ----------------------------------------------------------------------------
+macro_rules! try_from_enum_u8 {
+    ($enum_name: ident, $str_name: expr) => {
+        impl TryFrom<u8> for $enum_name {
+            type Error = InvalidValue;
+
+            #[inline]
+            fn try_from(value: u8) -> Result<$enum_name, InvalidValue> {
+                $enum_name::get(value).ok_or(InvalidValue {
+                    name: $str_name,
+                    value,
+                })
+            }
+        }
+    }
+}
+
 // ----------------------------------------------------------------------------
 // Implement the Serialize and Deserialize traits on a u8 enum.
 // ----------------------------------------------------------------------------
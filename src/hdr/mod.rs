@@ -41,7 +41,7 @@
 //!  6       EprOk    Created EPR pair.
 //!  7       MeasOut  Measurement outcome.
 //!  8       GetTime  Get creation time of qubit.
-//!  9       InfTime  Get timing informaiton.
+//!  9       InfTime  Inform about creation time of qubit.
 //!  10      NewOk    Created new qubit.
 //!  11      Mix      Multiple header types will follow.
 //!  12      If       Perform a conditional action.
@@ -80,13 +80,13 @@
 //! return additional messages, as described below:
 //!
 //! - New: Returns a NewOk reply followed by an Extra Qubit header with the
-//!        qubit ID.
+//!   qubit ID.
 //! - Measure(InPlace): Returns a MeasOut message followed by a Measurement
-//!                     Outcome header containing the measurement outcome.
+//!   Outcome header containing the measurement outcome.
 //! - Recv: Returns a Recv reply followed by an Extra Qubit header with the
-//!         qubit ID.
+//!   qubit ID.
 //! - Epr(Recv): Returns an EprOk reply by an Extra Qubit header and an
-//!              Entanglement Information header.
+//!   Entanglement Information header.
 //!
 //! ## CQC Command Header Instruction Types
 //!
@@ -109,9 +109,9 @@
 //!  11      Z               Pauli Z.
 //!  12      Y               Pauli Y.
 //!  13      T               T Gate.
-//!  14      RotX            Rotation over angle around X in pi/256 increments.
-//!  15      RotY            Rotation over angle around Y in pi/256 increments.
-//!  16      RotZ            Rotation over angle around Z in pi/256 increments.
+//!  14      RotX            Rotation around X in 2pi/256 increments.
+//!  15      RotY            Rotation around Y in 2pi/256 increments.
+//!  16      RotZ            Rotation around Z in 2pi/256 increments.
 //!  17      H               Hadamard Gate.
 //!  18      K               K Gate - taking computational to Y eigenbasis.
 //!
@@ -155,7 +155,7 @@
 //! ```text
 //! Field     Length     Meaning
 //! -----     ------     -------
-//! step      1 byte     Angle step of rotation (increments of 1/256).
+//! step      1 byte     Angle step of rotation (increments of 2pi/256).
 //! ```
 //!
 //! # CQC Extra Qubit Header
@@ -169,6 +169,17 @@
 //! qubit_id  2 bytes    ID of the target qubit.
 //! ```
 //!
+//! # CQC Sequence Header
+//!
+//! Additional header used to announce the length of the commands that follow
+//! a command with the ACTION option set.
+//!
+//! ```text
+//! Field       Length     Meaning
+//! -----       ------     -------
+//! cmd_length  1 byte     Length in bytes of the following commands.
+//! ```
+//!
 //! # CQC Communication Header
 //!
 //! Additional header used to send to which node to send information to. Used
@@ -306,10 +317,21 @@
 extern crate serde;
 
 use self::serde::de;
+use error::InvalidValue;
+#[cfg(feature = "std")]
+use std::cell::Cell;
+use std::convert::TryFrom;
 use std::fmt;
 use std::fmt::Display;
-
-use self::serde::de::Visitor;
+#[cfg(feature = "std")]
+use std::net::{Ipv4Addr, SocketAddrV4};
+#[cfg(feature = "std")]
+use std::thread::LocalKey;
+#[cfg(feature = "std")]
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use self::serde::de::{Unexpected, Visitor};
+use self::serde::ser::SerializeStruct;
 use self::serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 #[macro_use]
@@ -317,12 +339,17 @@ mod macros;
 
 /// # CQC Version
 ///
-/// The current supported versions are: 2.
-/// The currently unsupported versions are: 0, 1.
+/// The current supported versions are: 1, 2, 3.
+/// The currently unsupported versions are: 0.
+///
+/// Packets built by this crate use version 2, but packets of any supported
+/// version are decoded so that older and newer backends can be talked to.
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Version {
+    V1 = 1,
     V2 = 2,
+    V3 = 3,
 }
 
 impl Version {
@@ -331,7 +358,9 @@ impl Version {
     #[inline]
     pub fn get(value: u8) -> Option<Version> {
         let version = match value {
+            1 => Version::V1,
             2 => Version::V2,
+            3 => Version::V3,
             _ => return None,
         };
 
@@ -340,6 +369,7 @@ impl Version {
 }
 
 serde_enum_u8!(Version, VersionVisitor, "CQC version");
+try_from_enum_u8!(Version, "CQC version");
 
 /// # CQC Header
 ///
@@ -360,7 +390,7 @@ serde_enum_u8!(Version, VersionVisitor, "CQC version");
 ///  - Command
 ///  - Factory
 ///  - GetTime
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct CqcHdr {
     pub version: Version,
     pub msg_type: MsgType,
@@ -370,6 +400,16 @@ pub struct CqcHdr {
 
 def_len!(CqcHdr, 8);
 
+impl Display for CqcHdr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "CQC v{} {} app_id={} len={}",
+            self.version as u8, self.msg_type, self.app_id, self.length
+        )
+    }
+}
+
 /// # CQC Header Message Types
 ///
 /// The supported message types.  They are split into normal types (Tp) and
@@ -387,7 +427,7 @@ def_len!(CqcHdr, 8);
 ///  6       EprOk    Created EPR pair.
 ///  7       MeasOut  Measurement outcome.
 ///  8       GetTime  Get creation time of qubit.
-///  9       InfTime  Get timing informaiton.
+///  9       InfTime  Inform about creation time of qubit.
 ///  10      NewOk    Created new qubit.
 ///  11      Mix      Multiple header types will follow.
 ///  12      If       Perform a conditional action.
@@ -399,10 +439,16 @@ def_len!(CqcHdr, 8);
 ///  24      InUse    Qubit already in use.
 ///  25      Unknown  Unknown qubit ID.
 /// ```
-#[derive(Copy, Clone, Debug, Display, PartialEq)]
+///
+/// A message type that is not known to this version of the crate is only
+/// ever produced by a lenient `Decoder` and is represented by `Other`.  New
+/// variants may be added as the CQC specification grows.
+#[derive(Copy, Clone, Debug, Display, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum MsgType {
     Tp(Tp),
     Err(Err),
+    Other(u8),
 }
 
 impl From<MsgType> for u8 {
@@ -410,6 +456,7 @@ impl From<MsgType> for u8 {
         match msg_type {
             MsgType::Tp(val) => val as u8,
             MsgType::Err(val) => val as u8,
+            MsgType::Other(val) => val,
         }
     }
 }
@@ -441,18 +488,17 @@ macro_rules! def_is_err {
 impl MsgType {
     #[inline]
     pub fn is_tp(&self) -> bool {
-        match self {
-            &MsgType::Tp(_) => true,
-            &MsgType::Err(_) => false,
-        }
+        matches!(*self, MsgType::Tp(_))
     }
 
     #[inline]
     pub fn is_err(&self) -> bool {
-        match self {
-            &MsgType::Tp(_) => false,
-            &MsgType::Err(_) => true,
-        }
+        matches!(*self, MsgType::Err(_))
+    }
+
+    #[inline]
+    pub fn is_other(&self) -> bool {
+        matches!(*self, MsgType::Other(_))
     }
 
     def_is_tp!(Tp::Hello, is_hello);
@@ -490,6 +536,38 @@ impl MsgType {
 
         Some(msg_type)
     }
+
+    /// Convert an 8-bit value to a message type while deserializing.  An
+    /// unknown value is returned as `Other` if a lenient `Decoder` is
+    /// decoding on this thread.
+    fn get_or_other(value: u8) -> Option<MsgType> {
+        match MsgType::get(value) {
+            None if MsgType::accepting_other() => Some(MsgType::Other(value)),
+            msg_type => msg_type,
+        }
+    }
+
+    /// Deserialize an unknown message type as `Other` if `accept` is set
+    /// while `f` runs on this thread.  This is how a lenient `Decoder`
+    /// decodes packets of types unknown to this version of the crate.
+    #[cfg(feature = "std")]
+    pub(crate) fn accept_other<F, R>(accept: bool, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        with_setting(&ACCEPT_OTHER, accept, f)
+    }
+
+    #[cfg(feature = "std")]
+    fn accepting_other() -> bool {
+        ACCEPT_OTHER.with(Cell::get)
+    }
+
+    /// Without `std` there is no lenient `Decoder`.
+    #[cfg(not(feature = "std"))]
+    fn accepting_other() -> bool {
+        false
+    }
 }
 
 impl Serialize for MsgType {
@@ -498,14 +576,21 @@ impl Serialize for MsgType {
     where
         S: Serializer,
     {
-        match self {
-            &MsgType::Tp(tp) => serializer.serialize_u8(tp as u8),
-            &MsgType::Err(err) => serializer.serialize_u8(err as u8),
+        match *self {
+            MsgType::Tp(tp) => serializer.serialize_u8(tp as u8),
+            MsgType::Err(err) => serializer.serialize_u8(err as u8),
+            MsgType::Other(value) => serializer.serialize_u8(value),
         }
     }
 }
 
-deserialize_enum_u8!(MsgType, MsgTypeVisitor, "CQC message type");
+deserialize_enum_u8!(
+    MsgType,
+    MsgTypeVisitor,
+    "CQC message type",
+    MsgType::get_or_other
+);
+try_from_enum_u8!(MsgType, "CQC message type");
 
 /// # CQC Header Normal Message Types
 ///
@@ -523,13 +608,18 @@ deserialize_enum_u8!(MsgType, MsgTypeVisitor, "CQC message type");
 ///  6       EprOk    Created EPR pair.
 ///  7       MeasOut  Measurement outcome.
 ///  8       GetTime  Get creation time of qubit.
-///  9       InfTime  Get timing informaiton.
+///  9       InfTime  Inform about creation time of qubit.
 ///  10      NewOk    Created new qubit.
 ///  11      Mix      Multiple header types will follow.
 ///  12      If       Perform a conditional action.
 /// ```
+///
+/// A timing query is split between the two message types.  The client sends
+/// GetTime followed by a Command Header naming the qubit.  The backend
+/// replies with InfTime followed by a Time Info Header.  GetTime is therefore
+/// only decoded as a request and InfTime only as a response.
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, Display, PartialEq)]
+#[derive(Copy, Clone, Debug, Display, PartialEq, Eq, Hash)]
 pub enum Tp {
     Hello = 0,   // Alive check.
     Command = 1, // Execute a command list.
@@ -574,6 +664,7 @@ impl Tp {
 }
 
 serde_enum_u8!(Tp, TpVisitor, "CQC normal message type");
+try_from_enum_u8!(Tp, "CQC normal message type");
 
 /// # CQC Header Error Message Types
 ///
@@ -590,7 +681,7 @@ serde_enum_u8!(Tp, TpVisitor, "CQC normal message type");
 ///  25      Unknown  Unknown qubit ID.
 /// ```
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, Display, PartialEq)]
+#[derive(Copy, Clone, Debug, Display, PartialEq, Eq, Hash)]
 pub enum Err {
     General = 20, // General purpose error (no details).
     NoQubit = 21, // No more qubits available.
@@ -621,6 +712,7 @@ impl Err {
 }
 
 serde_enum_u8!(Err, ErrVisitor, "CQC error message type");
+try_from_enum_u8!(Err, "CQC error message type");
 
 /// # CQC Command Header
 ///
@@ -648,14 +740,16 @@ serde_enum_u8!(Err, ErrVisitor, "CQC error message type");
 /// return additional messages, as described below:
 ///
 /// - New: Returns a NewOk reply followed by an Extra Qubit header with the
-///        qubit ID.
+///   qubit ID.
 /// - Measure(InPlace): Returns a MeasOut message followed by a Measurement
-///                     Outcome header containing the measurement outcome.
+///   Outcome header containing the measurement outcome.
 /// - Recv: Returns a Recv reply followed by an Extra Qubit header with the
-///         qubit ID.
+///   qubit ID.
 /// - Epr(Recv): Returns an EprOk reply by an Extra Qubit header and an
-///              Entanglement Information header.
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+///   Entanglement Information header.
+#[derive(
+    Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash,
+)]
 pub struct CmdHdr {
     pub qubit_id: u16,
     pub instr: Cmd,
@@ -664,6 +758,16 @@ pub struct CmdHdr {
 
 def_len!(CmdHdr, 4);
 
+impl Display for CmdHdr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "CMD {} qubit_id={} options={:?}",
+            self.instr, self.qubit_id, self.options
+        )
+    }
+}
+
 /// # CQC Command Header Instruction Types
 ///
 /// The supported CQC instructions.
@@ -685,9 +789,9 @@ def_len!(CmdHdr, 4);
 ///  11      Z               Pauli Z.
 ///  12      Y               Pauli Y.
 ///  13      T               T Gate.
-///  14      RotX            Rotation over angle around X in pi/256 increments.
-///  15      RotY            Rotation over angle around Y in pi/256 increments.
-///  16      RotZ            Rotation over angle around Z in pi/256 increments.
+///  14      RotX            Rotation around X in 2pi/256 increments.
+///  15      RotY            Rotation around Y in 2pi/256 increments.
+///  16      RotZ            Rotation around Z in 2pi/256 increments.
 ///  17      H               Hadamard Gate.
 ///  18      K               K Gate - taking computational to Y eigenbasis.
 ///
@@ -698,7 +802,7 @@ def_len!(CmdHdr, 4);
 ///  23      Release         Release a qubit.
 /// ```
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, Display, PartialEq, Eq, Hash)]
 pub enum Cmd {
     I = 0,              // Identity (do nothing, wait one step).
     New = 1,            // Ask for a new qubit.
@@ -714,9 +818,9 @@ pub enum Cmd {
     Z = 11,    // Pauli Z.
     Y = 12,    // Pauli Y.
     T = 13,    // T Gate.
-    RotX = 14, // Rotation over angle around X in pi/256 increments.
-    RotY = 15, // Rotation over angle around Y in pi/256 increments.
-    RotZ = 16, // Rotation over angle around Z in pi/256 increments.
+    RotX = 14, // Rotation around X in 2pi/256 increments.
+    RotY = 15, // Rotation around Y in 2pi/256 increments.
+    RotZ = 16, // Rotation around Z in 2pi/256 increments.
     H = 17,    // Hadamard Gate.
     K = 18,    // K Gate - taking computational to Y eigenbasis.
 
@@ -727,6 +831,14 @@ pub enum Cmd {
     Release = 23,  // Release a qubit.
 }
 
+impl Default for Cmd {
+    /// The identity, whose value is 0.
+    #[inline]
+    fn default() -> Cmd {
+        Cmd::I
+    }
+}
+
 impl Cmd {
     /// Convert an 8-bit value to a command type.  Returns `None` if the value
     /// does not correspond to a valid command type.
@@ -764,9 +876,44 @@ impl Cmd {
 
         Some(command)
     }
+
+    /// Check whether the instruction returns a message even if the notify
+    /// option is not set.  All other instructions only reply with Done when
+    /// notify is set.
+    ///
+    /// - New and Allocate reply with NewOk.
+    /// - Measure(InPlace) replies with MeasOut.
+    /// - Recv replies with Recv.
+    /// - Epr(Recv) replies with EprOk.
+    #[inline]
+    pub fn always_responds(&self) -> bool {
+        matches!(
+            *self,
+            Cmd::New
+                | Cmd::Allocate
+                | Cmd::Measure
+                | Cmd::MeasureInplace
+                | Cmd::Recv
+                | Cmd::Epr
+                | Cmd::EprRecv
+        )
+    }
 }
 
 serde_enum_u8!(Cmd, CmdVisitor, "CQC instruction type");
+try_from_enum_u8!(Cmd, "CQC instruction type");
+
+/// Length of the extra header that must follow a Command Header for the given
+/// instruction.  Returns 0 for instructions that take no extra header.
+#[inline]
+pub const fn xtra_len(cmd: Cmd) -> u32 {
+    match cmd {
+        Cmd::RotX | Cmd::RotY | Cmd::RotZ => RotHdr::hdr_len(),
+        Cmd::Cnot | Cmd::Cphase => QubitHdr::hdr_len(),
+        Cmd::Send | Cmd::Epr => CommHdr::hdr_len(),
+        _ => 0,
+    }
+}
 
 bitflags! {
     /// # CQC Command Header options
@@ -781,6 +928,10 @@ bitflags! {
     /// 0x04     Block    Block until command is done.
     /// 0x08     IfThen   Execute command after done.
     /// ```
+    ///
+    /// The CQC specification does not forbid any combination of flags, so
+    /// option sets may be combined freely with `|`.
+    #[derive(Default)]
     pub struct CmdOpt: u8 {
         const NOTIFY = 0x01;
         const ACTION = 0x02;
@@ -799,6 +950,51 @@ impl CmdOpt {
     def_get_flag!(CmdOpt, ACTION, get_action);
     def_get_flag!(CmdOpt, BLOCK, get_block);
     def_get_flag!(CmdOpt, IFTHEN, get_ifthen);
+
+    /// Options requesting a notification once the command has completed.
+    #[inline]
+    pub fn notify() -> CmdOpt {
+        CmdOpt::NOTIFY
+    }
+
+    /// Options requesting a notification once the command has completed and
+    /// blocking until then.
+    #[inline]
+    pub fn notify_block() -> CmdOpt {
+        CmdOpt::NOTIFY | CmdOpt::BLOCK
+    }
+
+    /// Construct options with each flag set as given.
+    pub fn from_flags(
+        notify: bool,
+        action: bool,
+        block: bool,
+        ifthen: bool,
+    ) -> CmdOpt {
+        let mut opt = CmdOpt::empty();
+        opt.set(CmdOpt::NOTIFY, notify);
+        opt.set(CmdOpt::ACTION, action);
+        opt.set(CmdOpt::BLOCK, block);
+        opt.set(CmdOpt::IFTHEN, ifthen);
+        opt
+    }
+
+    /// Every flag together with its name.
+    const NAMES: [(CmdOpt, &'static str); 4] = [
+        (CmdOpt::NOTIFY, "NOTIFY"),
+        (CmdOpt::ACTION, "ACTION"),
+        (CmdOpt::BLOCK, "BLOCK"),
+        (CmdOpt::IFTHEN, "IFTHEN"),
+    ];
+
+    /// The names of the flags that are set, in order of increasing bit value.
+    pub fn iter_flags(&self) -> impl Iterator<Item = &'static str> {
+        let opt = *self;
+        CmdOpt::NAMES
+            .iter()
+            .filter(move |&&(flag, _)| opt.contains(flag))
+            .map(|&(_, name)| name)
+    }
 }
 
 serde_option_u8!(CmdOpt, CmdOptVisitor, "command");
@@ -815,7 +1011,9 @@ serde_option_u8!(CmdOpt, CmdOptVisitor, "command");
 /// -----     ------     -------
 /// ref_id    4 bytes    Reference ID for the measurement` value.
 /// ```
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(
+    Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash,
+)]
 pub struct AssignHdr {
     pub ref_id: u32,
 }
@@ -829,15 +1027,52 @@ def_len!(AssignHdr, 4);
 /// ```text
 /// Field     Length     Meaning
 /// -----     ------     -------
-/// step      1 byte     Angle step of rotation (increments of 1/256).
+/// step      1 byte     Angle step of rotation (increments of 2pi/256).
 /// ```
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(
+    Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash,
+)]
 pub struct RotHdr {
     pub step: u8,
 }
 
 def_len!(RotHdr, 1);
 
+#[cfg(feature = "std")]
+impl RotHdr {
+    /// The angle of a single step in radians.
+    const STEP: f64 = 2.0 * ::std::f64::consts::PI / 256.0;
+
+    /// Construct a Rotation Header for the given angle in radians.  The angle
+    /// is rounded to the nearest step and taken modulo a full turn.
+    #[inline]
+    pub fn from_radians(angle: f64) -> RotHdr {
+        let step = (angle / RotHdr::STEP).round() as i64;
+        RotHdr {
+            step: step.rem_euclid(256) as u8,
+        }
+    }
+
+    /// Construct a Rotation Header for the given angle in degrees.  The angle
+    /// is rounded to the nearest step and taken modulo a full turn.
+    #[inline]
+    pub fn from_degrees(angle: f64) -> RotHdr {
+        RotHdr::from_radians(angle.to_radians())
+    }
+
+    /// The rotation angle in radians, in the range [0, 2pi).
+    #[inline]
+    pub fn radians(&self) -> f64 {
+        f64::from(self.step) * RotHdr::STEP
+    }
+
+    /// The rotation angle in degrees, in the range [0, 360).
+    #[inline]
+    pub fn degrees(&self) -> f64 {
+        self.radians().to_degrees()
+    }
+}
+
 /// # CQC Extra Qubit Header
 ///
 /// Additional header used to send the qubit_id of a secondary qubit for two
@@ -848,13 +1083,34 @@ def_len!(RotHdr, 1);
 /// -----     ------     -------
 /// qubit_id  2 bytes    ID of the target qubit.
 /// ```
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(
+    Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash,
+)]
 pub struct QubitHdr {
     pub qubit_id: u16,
 }
 
 def_len!(QubitHdr, 2);
 
+/// # CQC Sequence Header
+///
+/// Additional header used to announce the length of the commands that follow
+/// a command with the ACTION option set.
+///
+/// ```text
+/// Field       Length     Meaning
+/// -----       ------     -------
+/// cmd_length  1 byte     Length in bytes of the following commands.
+/// ```
+#[derive(
+    Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash,
+)]
+pub struct SeqHdr {
+    pub cmd_length: u8,
+}
+
+def_len!(SeqHdr, 1);
+
 /// # CQC Communication Header
 ///
 /// Additional header used to send to which node to send information to. Used
@@ -869,7 +1125,10 @@ def_len!(QubitHdr, 2);
 ///                           control info.
 /// remote_node    4 bytes    IP of the remote node (IPv4).
 /// ```
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+///
+/// The fields are serialized in the order given by the `CommHdrLayout` of
+/// the `Encoder` doing the encoding, which is the order above by default.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct CommHdr {
     pub remote_app_id: u16,
     pub remote_port: u16,
@@ -878,6 +1137,149 @@ pub struct CommHdr {
 
 def_len!(CommHdr, 8);
 
+impl Serialize for CommHdr {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_struct("CommHdr", 3)?;
+        s.serialize_field("remote_app_id", &self.remote_app_id)?;
+        match CommHdrLayout::current() {
+            CommHdrLayout::AppPortNode => {
+                s.serialize_field("remote_port", &self.remote_port)?;
+                s.serialize_field("remote_node", &self.remote_node)?;
+            }
+            CommHdrLayout::AppNodePort => {
+                s.serialize_field("remote_node", &self.remote_node)?;
+                s.serialize_field("remote_port", &self.remote_port)?;
+            }
+        }
+        s.end()
+    }
+}
+
+impl CommHdr {
+    /// Construct a Communication Header addressing a node by its IPv4
+    /// address.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn with_addr(
+        remote_app_id: u16,
+        remote_port: u16,
+        addr: Ipv4Addr,
+    ) -> CommHdr {
+        CommHdr {
+            remote_app_id,
+            remote_port,
+            remote_node: u32::from(addr),
+        }
+    }
+
+    /// The IPv4 address of the remote node.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn remote_ipv4(&self) -> Ipv4Addr {
+        Ipv4Addr::from(self.remote_node)
+    }
+}
+
+impl Display for CommHdr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "COMM {}:{} app_id={}",
+            DottedQuad(self.remote_node),
+            self.remote_port,
+            self.remote_app_id
+        )
+    }
+}
+
+/// An IPv4 address in dotted-quad notation.  Unlike `Ipv4Addr` this is
+/// available without `std`.
+struct DottedQuad(u32);
+
+impl Display for DottedQuad {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [a, b, c, d] = self.0.to_be_bytes();
+        write!(f, "{}.{}.{}.{}", a, b, c, d)
+    }
+}
+
+/// # Communication Header layout
+///
+/// Revisions of the CQC specification disagree on the order of the last two
+/// fields of the Communication Header.  The layout documented above, and the
+/// one used by SimulaQron, is `AppPortNode`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CommHdrLayout {
+    /// remote_app_id, remote_port, remote_node.
+    #[default]
+    AppPortNode,
+    /// remote_app_id, remote_node, remote_port.
+    AppNodePort,
+}
+
+// Settings of the `Encoder` and `Decoder` which the serde implementations of
+// the headers need.  Serde offers no way to pass them down so they are set
+// for the duration of a single encode or decode on the current thread.
+#[cfg(feature = "std")]
+thread_local! {
+    /// The layout in which Communication Headers are serialized.  It is only
+    /// changed by `CommHdrLayout::apply`.
+    static COMM_HDR_LAYOUT: Cell<CommHdrLayout> =
+        const { Cell::new(CommHdrLayout::AppPortNode) };
+
+    /// Whether an unknown message type is deserialized as `MsgType::Other`.
+    /// It is only changed by `MsgType::accept_other`.
+    static ACCEPT_OTHER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Set a thread-local setting while `f` runs.  The previous value is
+/// restored afterwards, even if `f` panics.
+#[cfg(feature = "std")]
+fn with_setting<T, F, R>(key: &'static LocalKey<Cell<T>>, value: T, f: F) -> R
+where
+    T: Copy,
+    F: FnOnce() -> R,
+{
+    struct Restore<T: Copy + 'static>(&'static LocalKey<Cell<T>>, T);
+
+    impl<T: Copy> Drop for Restore<T> {
+        fn drop(&mut self) {
+            self.0.with(|setting| setting.set(self.1));
+        }
+    }
+
+    let previous = key.with(|setting| setting.replace(value));
+    let _restore = Restore(key, previous);
+    f()
+}
+
+impl CommHdrLayout {
+    /// Serialize every Communication Header in `self` layout while `f`
+    /// runs on this thread.  This is how the `Encoder` applies its layout.
+    #[cfg(feature = "std")]
+    pub(crate) fn apply<F, R>(self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        with_setting(&COMM_HDR_LAYOUT, self, f)
+    }
+
+    /// The layout in which Communication Headers are currently serialized.
+    #[cfg(feature = "std")]
+    fn current() -> CommHdrLayout {
+        COMM_HDR_LAYOUT.with(Cell::get)
+    }
+
+    /// Without `std` the layout cannot be changed.
+    #[cfg(not(feature = "std"))]
+    fn current() -> CommHdrLayout {
+        CommHdrLayout::AppPortNode
+    }
+}
+
 /// # CQC Factory Header
 ///
 /// Additional header used to send factory information. Factory commands are
@@ -890,7 +1292,9 @@ def_len!(CommHdr, 8);
 /// num_iter  1 byte     Number of iterations to do the sequence.
 /// options   1 byte     Options when executing the factory.
 /// ```
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(
+    Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash,
+)]
 pub struct FactoryHdr {
     pub num_iter: u8,
     pub options: FactoryOpt,
@@ -909,6 +1313,7 @@ bitflags! {
     /// 0x01     Notify   Send a notification when command completes.
     /// 0x04     Block    Block until factory is done.
     /// ```
+    #[derive(Default)]
     pub struct FactoryOpt: u8 {
         const NOTIFY = 0x01;
         const BLOCK = 0x04;
@@ -934,7 +1339,7 @@ serde_option_u8!(FactoryOpt, FactoryOptVisitor, "factory");
 /// -----     ------     -------
 /// meas_out  1 byte     Measurement outcome.
 /// ```
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct MeasOutHdr {
     pub meas_out: MeasOut,
 }
@@ -945,7 +1350,7 @@ def_len!(MeasOutHdr, 1);
 ///
 /// There are only two possible outcome values: 0 or 1.
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum MeasOut {
     Zero = 0,
     One = 1,
@@ -964,9 +1369,32 @@ impl MeasOut {
 
         Some(meas_out)
     }
+
+    /// The outcome as a boolean where One is `true`.
+    #[inline]
+    pub fn as_bool(&self) -> bool {
+        *self == MeasOut::One
+    }
+}
+
+impl From<MeasOut> for bool {
+    fn from(meas_out: MeasOut) -> Self {
+        meas_out.as_bool()
+    }
+}
+
+impl From<bool> for MeasOut {
+    fn from(value: bool) -> Self {
+        if value {
+            MeasOut::One
+        } else {
+            MeasOut::Zero
+        }
+    }
 }
 
 serde_enum_u8!(MeasOut, MeasOutVisitor, "Measurement Outcome");
+try_from_enum_u8!(MeasOut, "Measurement Outcome");
 
 /// # CQC Time Info Header
 ///
@@ -978,7 +1406,9 @@ serde_enum_u8!(MeasOut, MeasOutVisitor, "Measurement Outcome");
 /// -----     ------     -------
 /// datetime  8 bytes    Time of creation.
 /// ```
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(
+    Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash,
+)]
 pub struct TimeInfoHdr {
     pub datetime: u64,
 }
@@ -1014,7 +1444,9 @@ def_len!(TimeInfoHdr, 8);
 /// DF         1 byte     Directionality flag (0=Mid, 1=node_A, 2=node_B).
 /// align      1 byte     4 byte alignment.
 /// ```
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(
+    Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash,
+)]
 pub struct EntInfoHdr {
     pub node_a: u32,
     pub port_a: u16,
@@ -1032,6 +1464,154 @@ pub struct EntInfoHdr {
 
 def_len!(EntInfoHdr, 40);
 
+impl EntInfoHdr {
+    /// The directionality flag.  Returns `None` if the flag does not hold a
+    /// valid value.
+    #[inline]
+    pub fn directionality(&self) -> Option<Directionality> {
+        Directionality::get(self.df)
+    }
+
+    /// The socket address of node A.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn addr_a(&self) -> SocketAddrV4 {
+        SocketAddrV4::new(Ipv4Addr::from(self.node_a), self.port_a)
+    }
+
+    /// The socket address of node B.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn addr_b(&self) -> SocketAddrV4 {
+        SocketAddrV4::new(Ipv4Addr::from(self.node_b), self.port_b)
+    }
+}
+
+impl Display for EntInfoHdr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ENT A={}:{} app_id={} B={}:{} app_id={} id={} timestamp={} \
+             tog={} goodness={} df={}",
+            DottedQuad(self.node_a),
+            self.port_a,
+            self.app_id_a,
+            DottedQuad(self.node_b),
+            self.port_b,
+            self.app_id_b,
+            self.id_ab,
+            self.timestamp,
+            self.tog,
+            self.goodness,
+            self.df
+        )
+    }
+}
+
+/// # Entanglement Record
+///
+/// The contents of an Entanglement Information Header in application-friendly
+/// types.  The timestamps are taken to be seconds since the Unix epoch and
+/// the directionality is `None` if the flag does not hold a valid value.
+#[cfg(feature = "std")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EntanglementRecord {
+    pub node_a: SocketAddrV4,
+    pub app_id_a: u16,
+    pub node_b: SocketAddrV4,
+    pub app_id_b: u16,
+    pub id_ab: u32,
+    pub timestamp: SystemTime,
+    pub tog: SystemTime,
+    pub goodness: u16,
+    pub directionality: Option<Directionality>,
+}
+
+#[cfg(feature = "std")]
+impl From<EntInfoHdr> for EntanglementRecord {
+    fn from(hdr: EntInfoHdr) -> Self {
+        EntanglementRecord {
+            node_a: hdr.addr_a(),
+            app_id_a: hdr.app_id_a,
+            node_b: hdr.addr_b(),
+            app_id_b: hdr.app_id_b,
+            id_ab: hdr.id_ab,
+            timestamp: UNIX_EPOCH + Duration::from_secs(hdr.timestamp),
+            tog: UNIX_EPOCH + Duration::from_secs(hdr.tog),
+            goodness: hdr.goodness,
+            directionality: hdr.directionality(),
+        }
+    }
+}
+
+/// Times before the Unix epoch are stored as zero and a missing
+/// directionality is stored as the invalid flag `0xFF`.
+#[cfg(feature = "std")]
+impl From<EntanglementRecord> for EntInfoHdr {
+    fn from(record: EntanglementRecord) -> Self {
+        EntInfoHdr {
+            node_a: u32::from(*record.node_a.ip()),
+            port_a: record.node_a.port(),
+            app_id_a: record.app_id_a,
+            node_b: u32::from(*record.node_b.ip()),
+            port_b: record.node_b.port(),
+            app_id_b: record.app_id_b,
+            id_ab: record.id_ab,
+            timestamp: unix_secs(record.timestamp),
+            tog: unix_secs(record.tog),
+            goodness: record.goodness,
+            df: record.directionality.map_or(0xFF, |df| df as u8),
+            align: 0,
+        }
+    }
+}
+
+/// Seconds since the Unix epoch, or zero for earlier times.
+#[cfg(feature = "std")]
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// # CQC Entanglement Directionality
+///
+/// Which party initialised the entanglement.
+///
+/// ```text
+/// Flag     Name     Meaning
+/// ----     ----     -------
+///  0       Mid      Created by a midpoint between the nodes.
+///  1       NodeA    Created by node A.
+///  2       NodeB    Created by node B.
+/// ```
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Directionality {
+    Mid = 0,
+    NodeA = 1,
+    NodeB = 2,
+}
+
+impl Directionality {
+    /// Convert an 8-bit value to a directionality flag.  Returns `None` if
+    /// the value does not correspond to a valid flag.
+    #[inline]
+    pub fn get(value: u8) -> Option<Directionality> {
+        let df = match value {
+            0 => Directionality::Mid,
+            1 => Directionality::NodeA,
+            2 => Directionality::NodeB,
+            _ => return None,
+        };
+
+        Some(df)
+    }
+}
+
+serde_enum_u8!(Directionality, DirectionalityVisitor, "Directionality flag");
+try_from_enum_u8!(Directionality, "Directionality flag");
+
 /// # CQC Type Header
 ///
 /// A top-level CQC header of type Mix may be followed by multiple other header
@@ -1043,7 +1623,11 @@ def_len!(EntInfoHdr, 40);
 /// type      1 byte     Type of next header (except Mix).
 /// length    4 bytes    Number of bytes until the next type header.
 /// ```
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+///
+/// Type Headers are not extra headers of a command.  Each one starts a
+/// `MixSeg` of a Mix program, which is most easily built with
+/// `builder::Client::mix`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct TypeHdr {
     pub hdr_type: Tp,
     pub length: u32,
@@ -1065,7 +1649,7 @@ def_len!(TypeHdr, 5);
 /// right_operand  4 bytes    Reference ID or value of second operand.
 /// length         4 bytes    Length in bytes of following command.
 /// ```
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct IfHdr {
     pub left_op: u32,
     pub operator: CmpType,
@@ -1076,6 +1660,52 @@ pub struct IfHdr {
 
 def_len!(IfHdr, 14);
 
+impl IfHdr {
+    /// Condition that the value stored under `left_ref` equals `value`.
+    /// `cmd_len` is the length of the command that follows.
+    #[inline]
+    pub fn eq_value(left_ref: u32, value: u32, cmd_len: u32) -> IfHdr {
+        IfHdr::cmp(left_ref, CmpType::Eq, OpType::Value, value, cmd_len)
+    }
+
+    /// Condition that the values stored under `left_ref` and `right_ref` are
+    /// equal.  `cmd_len` is the length of the command that follows.
+    #[inline]
+    pub fn eq_ref(left_ref: u32, right_ref: u32, cmd_len: u32) -> IfHdr {
+        IfHdr::cmp(left_ref, CmpType::Eq, OpType::RefId, right_ref, cmd_len)
+    }
+
+    /// Condition that the value stored under `left_ref` differs from `value`.
+    /// `cmd_len` is the length of the command that follows.
+    #[inline]
+    pub fn ineq_value(left_ref: u32, value: u32, cmd_len: u32) -> IfHdr {
+        IfHdr::cmp(left_ref, CmpType::InEq, OpType::Value, value, cmd_len)
+    }
+
+    /// Condition that the values stored under `left_ref` and `right_ref`
+    /// differ.  `cmd_len` is the length of the command that follows.
+    #[inline]
+    pub fn ineq_ref(left_ref: u32, right_ref: u32, cmd_len: u32) -> IfHdr {
+        IfHdr::cmp(left_ref, CmpType::InEq, OpType::RefId, right_ref, cmd_len)
+    }
+
+    fn cmp(
+        left_op: u32,
+        operator: CmpType,
+        right_op_t: OpType,
+        right_op: u32,
+        length: u32,
+    ) -> IfHdr {
+        IfHdr {
+            left_op,
+            operator,
+            right_op_t,
+            right_op,
+            length,
+        }
+    }
+}
+
 /// ## CQC If Header Operator Types
 ///
 /// ```text
@@ -1085,7 +1715,7 @@ def_len!(IfHdr, 14);
 ///  1       InEq     Compare for inequality.
 /// ```
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum CmpType {
     Eq = 0,
     InEq = 1,
@@ -1107,6 +1737,7 @@ impl CmpType {
 }
 
 serde_enum_u8!(CmpType, CmpTypeVisitor, "Comparison Operator Type");
+try_from_enum_u8!(CmpType, "Comparison Operator Type");
 
 /// ## CQC If Header Right Operand Types
 ///
@@ -1117,7 +1748,7 @@ serde_enum_u8!(CmpType, CmpTypeVisitor, "Comparison Operator Type");
 ///  1       RefId    Right operand holds reference ID.
 /// ```
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum OpType {
     Value = 0,
     RefId = 1,
@@ -1139,16 +1770,17 @@ impl OpType {
 }
 
 serde_enum_u8!(OpType, OpTypeVisitor, "Operand Type");
+try_from_enum_u8!(OpType, "Operand Type");
 
 // ----------------------------------------------------------------------------
 // Tests.
 // ----------------------------------------------------------------------------
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     extern crate bincode;
 
-    use self::bincode::serialize;
+    use self::bincode::{deserialize, serialize};
     use super::*;
 
     #[test]
@@ -1172,6 +1804,114 @@ mod tests {
         assert_eq!(serialize(&cmd_hdr).unwrap().len() as u32, cmd_hdr.len());
     }
 
+    #[test]
+    fn cmd_xtra_len() {
+        assert_eq!(xtra_len(Cmd::Send), CommHdr::hdr_len());
+        assert_eq!(xtra_len(Cmd::RotX), RotHdr::hdr_len());
+        assert_eq!(xtra_len(Cmd::Cnot), QubitHdr::hdr_len());
+        assert_eq!(xtra_len(Cmd::H), 0);
+    }
+
+    #[test]
+    fn cmd_always_responds() {
+        assert!(Cmd::Recv.always_responds());
+        assert!(Cmd::Measure.always_responds());
+        assert!(!Cmd::X.always_responds());
+        assert!(!Cmd::Send.always_responds());
+    }
+
+    #[test]
+    fn try_from_u8() {
+        assert_eq!(Cmd::try_from(Cmd::H as u8), Ok(Cmd::H));
+        assert_eq!(Tp::try_from(Tp::Mix as u8), Ok(Tp::Mix));
+        assert_eq!(Err::try_from(Err::Timeout as u8), Ok(Err::Timeout));
+        assert_eq!(
+            MsgType::try_from(Err::Unsupp as u8),
+            Ok(MsgType::Err(Err::Unsupp))
+        );
+        assert_eq!(Version::try_from(2), Ok(Version::V2));
+        assert_eq!(MeasOut::try_from(1), Ok(MeasOut::One));
+        assert_eq!(CmpType::try_from(1), Ok(CmpType::InEq));
+        assert_eq!(OpType::try_from(0), Ok(OpType::Value));
+
+        let err = Version::try_from(0xFF).unwrap_err();
+        assert_eq!(
+            err,
+            InvalidValue {
+                name: "CQC version",
+                value: 0xFF,
+            }
+        );
+        assert_eq!(err.to_string(), "Invalid CQC version: 255");
+    }
+
+    #[test]
+    fn cmd_opt_constructors() {
+        assert_eq!(CmdOpt::notify(), *CmdOpt::empty().set_notify());
+        assert_eq!(
+            CmdOpt::notify_block(),
+            *CmdOpt::empty().set_notify().set_block()
+        );
+        assert_eq!(
+            CmdOpt::from_flags(true, false, true, false),
+            CmdOpt::notify_block()
+        );
+        assert_eq!(CmdOpt::from_flags(false, true, false, true).bits(), 0x0A);
+        assert!(CmdOpt::from_flags(false, false, false, false).is_empty());
+    }
+
+    #[test]
+    fn if_hdr_constructors() {
+        let hdr = IfHdr::eq_value(1, 7, 4);
+        assert_eq!(hdr.left_op, 1);
+        assert_eq!(hdr.operator, CmpType::Eq);
+        assert_eq!(hdr.right_op_t, OpType::Value);
+        assert_eq!(hdr.right_op, 7);
+        assert_eq!(hdr.length, 4);
+
+        let hdr = IfHdr::eq_ref(1, 2, 4);
+        assert_eq!(hdr.operator, CmpType::Eq);
+        assert_eq!(hdr.right_op_t, OpType::RefId);
+        assert_eq!(hdr.right_op, 2);
+
+        let hdr = IfHdr::ineq_value(1, 7, 4);
+        assert_eq!(hdr.operator, CmpType::InEq);
+        assert_eq!(hdr.right_op_t, OpType::Value);
+
+        let hdr = IfHdr::ineq_ref(1, 2, 4);
+        assert_eq!(hdr.operator, CmpType::InEq);
+        assert_eq!(hdr.right_op_t, OpType::RefId);
+    }
+
+    #[test]
+    fn default_hdrs() {
+        assert!(CmdOpt::default().is_empty());
+        assert!(FactoryOpt::default().is_empty());
+
+        let mut cmd_hdr = CmdHdr::default();
+        assert_eq!(cmd_hdr.instr, Cmd::I);
+        cmd_hdr.qubit_id = 3;
+        assert_eq!(
+            cmd_hdr,
+            CmdHdr {
+                qubit_id: 3,
+                instr: Cmd::I,
+                options: CmdOpt::empty(),
+            }
+        );
+
+        let ent_info_hdr = EntInfoHdr::default();
+        assert!(serialize(&ent_info_hdr).unwrap().iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn cmd_opt_iter_flags() {
+        let flags: Vec<_> = CmdOpt::notify_block().iter_flags().collect();
+        assert_eq!(flags, vec!["NOTIFY", "BLOCK"]);
+        assert_eq!(CmdOpt::empty().iter_flags().count(), 0);
+        assert_eq!(CmdOpt::all().iter_flags().count(), 4);
+    }
+
     #[test]
     fn assign_hdr_ser_size() {
         let assign_hdr = AssignHdr { ref_id: 0 };
@@ -1206,6 +1946,159 @@ mod tests {
         assert_eq!(serialize(&comm_hdr).unwrap().len() as u32, comm_hdr.len());
     }
 
+    #[test]
+    fn rot_hdr_angle() {
+        use std::f64::consts::PI;
+
+        assert_eq!(RotHdr::from_radians(PI / 2.0).step, 64);
+        assert_eq!(RotHdr::from_radians(-PI / 4.0).step, 224);
+        assert_eq!(RotHdr::from_radians(2.0 * PI).step, 0);
+        assert_eq!(RotHdr::from_degrees(180.0).step, 128);
+        assert_eq!(RotHdr::from_degrees(1.0).step, 1);
+
+        assert!((RotHdr { step: 128 }.radians() - PI).abs() < 1e-12);
+        assert!((RotHdr { step: 32 }.degrees() - 45.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn seq_hdr_ser_size() {
+        let seq_hdr = SeqHdr { cmd_length: 0 };
+        assert_eq!(serialize(&seq_hdr).unwrap().len() as u32, seq_hdr.len());
+    }
+
+    // Pin the offset of every field so the layout cannot silently change.
+    #[test]
+    #[allow(deprecated)]
+    fn comm_hdr_offsets() {
+        let comm_hdr = CommHdr {
+            remote_app_id: 0x01_02,
+            remote_port: 0x03_04,
+            remote_node: 0x05_06_07_08,
+        };
+        let bytes =
+            bincode::config().big_endian().serialize(&comm_hdr).unwrap();
+        assert_eq!(bytes[0..2], [0x01, 0x02]);
+        assert_eq!(bytes[2..4], [0x03, 0x04]);
+        assert_eq!(bytes[4..8], [0x05, 0x06, 0x07, 0x08]);
+    }
+
+    #[test]
+    fn comm_hdr_ipv4() {
+        let addr = Ipv4Addr::new(127, 0, 0, 1);
+        let comm_hdr = CommHdr::with_addr(10, 8804, addr);
+        assert_eq!(comm_hdr.remote_node, 0x7F_00_00_01);
+        assert_eq!(comm_hdr.remote_ipv4(), addr);
+    }
+
+    #[test]
+    fn comm_hdr_hash_key() {
+        use std::collections::HashSet;
+
+        let addr = Ipv4Addr::new(127, 0, 0, 1);
+        let mut endpoints = HashSet::new();
+        assert!(endpoints.insert(CommHdr::with_addr(10, 8804, addr)));
+        assert!(endpoints.insert(CommHdr::with_addr(10, 8805, addr)));
+        assert!(!endpoints.insert(CommHdr::with_addr(10, 8804, addr)));
+        assert_eq!(endpoints.len(), 2);
+    }
+
+    #[test]
+    fn hdr_display() {
+        let cqc_hdr = CqcHdr {
+            version: Version::V2,
+            msg_type: MsgType::Tp(Tp::Command),
+            app_id: 10,
+            length: 4,
+        };
+        assert_eq!(cqc_hdr.to_string(), "CQC v2 Command app_id=10 len=4");
+
+        let cmd_hdr = CmdHdr {
+            qubit_id: 1,
+            instr: Cmd::New,
+            options: *CmdOpt::empty().set_notify(),
+        };
+        assert_eq!(cmd_hdr.to_string(), "CMD New qubit_id=1 options=NOTIFY");
+
+        let addr = Ipv4Addr::new(127, 0, 0, 1);
+        let comm_hdr = CommHdr::with_addr(10, 8804, addr);
+        assert_eq!(comm_hdr.to_string(), "COMM 127.0.0.1:8804 app_id=10");
+
+        let ent_info_hdr = EntInfoHdr {
+            node_a: u32::from(addr),
+            port_a: 8803,
+            app_id_a: 10,
+            node_b: u32::from(Ipv4Addr::new(10, 0, 0, 2)),
+            port_b: 8804,
+            app_id_b: 11,
+            id_ab: 7,
+            timestamp: 100,
+            tog: 200,
+            goodness: 99,
+            df: 1,
+            align: 0,
+        };
+        assert_eq!(
+            ent_info_hdr.to_string(),
+            "ENT A=127.0.0.1:8803 app_id=10 B=10.0.0.2:8804 app_id=11 id=7 \
+             timestamp=100 tog=200 goodness=99 df=1"
+        );
+    }
+
+    #[test]
+    fn entanglement_record() {
+        let ent_info_hdr = EntInfoHdr {
+            node_a: u32::from(Ipv4Addr::new(127, 0, 0, 1)),
+            port_a: 8803,
+            app_id_a: 10,
+            node_b: u32::from(Ipv4Addr::new(10, 0, 0, 2)),
+            port_b: 8804,
+            app_id_b: 11,
+            id_ab: 7,
+            timestamp: 100,
+            tog: 200,
+            goodness: 99,
+            df: 2,
+            align: 0,
+        };
+
+        assert_eq!(
+            ent_info_hdr.addr_a(),
+            SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 8803)
+        );
+        assert_eq!(
+            ent_info_hdr.addr_b(),
+            SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 8804)
+        );
+
+        let record = EntanglementRecord::from(ent_info_hdr);
+        assert_eq!(
+            record.node_a,
+            SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 8803)
+        );
+        assert_eq!(
+            record.node_b,
+            SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 8804)
+        );
+        assert_eq!(record.timestamp, UNIX_EPOCH + Duration::from_secs(100));
+        assert_eq!(record.tog, UNIX_EPOCH + Duration::from_secs(200));
+        assert_eq!(record.directionality, Some(Directionality::NodeB));
+
+        let ent_info_hdr = EntInfoHdr::from(record);
+        assert_eq!(ent_info_hdr.node_a, 0x7F_00_00_01);
+        assert_eq!(ent_info_hdr.port_b, 8804);
+        assert_eq!(ent_info_hdr.timestamp, 100);
+        assert_eq!(ent_info_hdr.tog, 200);
+        assert_eq!(ent_info_hdr.df, 2);
+    }
+
+    #[test]
+    fn directionality_ser() {
+        let df = Directionality::NodeB;
+        assert_eq!(serialize(&df).unwrap(), vec![2]);
+        assert_eq!(Directionality::get(2), Some(df));
+        assert_eq!(Directionality::get(3), None);
+    }
+
     #[test]
     fn factory_hdr_ser_size() {
         let factory_hdr = FactoryHdr {
@@ -1229,6 +2122,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn meas_out_hdr_invalid() {
+        let err = deserialize::<MeasOutHdr>(&[0x02]).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid Measurement Outcome: 2");
+    }
+
+    #[test]
+    fn meas_out_bool() {
+        assert!(MeasOut::One.as_bool());
+        assert!(!bool::from(MeasOut::Zero));
+        assert_eq!(MeasOut::from(true), MeasOut::One);
+        assert_eq!(MeasOut::from(false), MeasOut::Zero);
+    }
+
     #[test]
     fn time_info_hdr_ser_size() {
         let time_info_hdr = TimeInfoHdr { datetime: 0 };
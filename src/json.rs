@@ -0,0 +1,118 @@
+//! # JSON Conversion
+//!
+//! Conversion of packets and headers to and from JSON for logging and for
+//! test fixtures.  A packet is represented as the array of its headers in the
+//! order in which they are sent.
+//!
+//! JSON does not use the big-endian wire layout of CQC and is therefore only
+//! meant for logging and debugging.  Use the `Encoder` and `Decoder` to
+//! exchange packets with a CQC backend.
+
+extern crate serde_json;
+
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+
+pub use self::serde_json::Error;
+
+/// Convert a packet or header to a JSON string.
+pub fn to_json_string<T>(value: &T) -> Result<String, Error>
+where
+    T: Serialize,
+{
+    serde_json::to_string(value)
+}
+
+/// Read a packet or header from a JSON string.
+pub fn from_json_str<T>(s: &str) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    serde_json::from_str(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use builder::{Client, RemoteId, Server};
+    use hdr::*;
+    use {ReqCmd, Request, Response, RspInfo, XtraHdr};
+
+    fn req_cmd_h(qubit_id: u16) -> ReqCmd {
+        ReqCmd {
+            cmd_hdr: CmdHdr {
+                qubit_id,
+                instr: Cmd::H,
+                options: CmdOpt::empty(),
+            },
+            xtra_hdr: XtraHdr::None,
+        }
+    }
+
+    fn round_trip_req(request: &Request) {
+        let json = to_json_string(request).unwrap();
+        let decoded: Request = from_json_str(&json).unwrap();
+        assert_eq!(&decoded, request);
+    }
+
+    fn round_trip_rsp(response: &Response) {
+        let json = to_json_string(response).unwrap();
+        let decoded: Response = from_json_str(&json).unwrap();
+        assert_eq!(&decoded, response);
+    }
+
+    #[test]
+    fn request_round_trip() {
+        let client = Client::new(10);
+
+        round_trip_req(&client.hello());
+        round_trip_req(&client.cmd_new(0, CmdOpt::notify()));
+        round_trip_req(&client.cmd_send(
+            1,
+            CmdOpt::empty(),
+            RemoteId {
+                remote_app_id: 5,
+                remote_node: 0x7F000001,
+                remote_port: 8004,
+            },
+        ));
+        round_trip_req(&client.cmd_rot_x(2, CmdOpt::empty(), 64));
+        round_trip_req(&client.factory(3, FactoryOpt::empty(), req_cmd_h(0)));
+        round_trip_req(&client.if_ref_cmp_ref(
+            0,
+            CmpType::Eq,
+            1,
+            req_cmd_h(2),
+        ));
+        round_trip_req(
+            &client
+                .mix()
+                .push_command(req_cmd_h(0))
+                .push_command(req_cmd_h(1))
+                .build(),
+        );
+    }
+
+    #[test]
+    fn response_round_trip() {
+        let server = Server::new(10);
+
+        round_trip_rsp(&server.done());
+        round_trip_rsp(&server.new_ok(3));
+        round_trip_rsp(&server.meas_out(MeasOut::One));
+
+        let mut new_ok = server.new_ok(3);
+        new_ok.notify = RspInfo::Qubits(vec![
+            QubitHdr { qubit_id: 3 },
+            QubitHdr { qubit_id: 4 },
+        ]);
+        new_ok.cqc_hdr.length = new_ok.notify.len();
+        round_trip_rsp(&new_ok);
+        round_trip_rsp(
+            &server.factory(vec![
+                server.new_ok(4),
+                server.meas_out(MeasOut::Zero),
+            ]),
+        );
+    }
+}
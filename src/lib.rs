@@ -32,6 +32,9 @@
 //! for generating CQC packets.  It should be used in conjunction with the CQC
 //! interface documentation in the `hdr` module.
 //!
+//! The most commonly used types are re-exported by the `prelude` module and
+//! can be imported with `use cqc::prelude::*;`.
+//!
 //! ### Encoding/decoding packets
 //!
 //! All headers in the `hdr` module implement `serde`'s `Serialize` and
@@ -44,13 +47,28 @@
 //! The library provides a `Response` struct which implements `Deserialize` and
 //! can be used to deserialize any response from the SimulaQron server.
 //!
+//! With the `json` feature the `json` module converts packets to and from
+//! JSON for logging and test fixtures.  JSON is not the CQC wire format.
+//!
+//! With the `tokio` feature the `async_io` module reads and writes packets on
+//! Tokio streams.
+//!
+//! ### `no_std` support
+//!
+//! The `std` feature is enabled by default.  Without it the crate only needs
+//! `alloc`.  The `hdr` and `builder` modules and the `Request` and `Response`
+//! types remain available, but the `Encoder`, the `Decoder`, and the
+//! `Ipv4Addr` helpers do not.
+//!
 //! ### Example
 //!
 //! The following example will create a qubit on one node and send it to
 //! another node.  Before running the example below start up the SimulaQron
-//! nodes with `$NETSIM/run/startAll.sh --nrnodes 2`.
+//! nodes with `$NETSIM/run/startAll.sh --nrnodes 2`.  The example uses
+//! `bincode` and `std::net` and so needs the `std` feature.
 //!
-//! ```no_run
+#![cfg_attr(feature = "std", doc = "```no_run")]
+#![cfg_attr(not(feature = "std"), doc = "```ignore")]
 //! extern crate bincode;
 //! extern crate cqc;
 //!
@@ -94,7 +112,7 @@
 //!     // Send the qubit to the remote node.
 //!     let request = client.cmd_send(
 //!         qubit_id,
-//!         *hdr::CmdOpt::empty().set_notify(),
+//!         hdr::CmdOpt::notify(),
 //!         builder::RemoteId {
 //!             remote_app_id: app_id,
 //!             remote_node: remote_host,
@@ -132,27 +150,75 @@
 //! }
 //! ```
 
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(feature = "std"))]
+#[macro_use]
+extern crate alloc;
+#[cfg(not(feature = "std"))]
+extern crate core as std;
+
+#[cfg(feature = "std")]
 extern crate bincode;
 #[macro_use]
 extern crate bitflags;
+#[cfg(feature = "resolve")]
+extern crate dns_lookup;
 #[macro_use]
 extern crate enum_display_derive;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 
+/// The items of the standard prelude which `alloc` provides.
+#[cfg(not(feature = "std"))]
+mod alloc_prelude {
+    pub use alloc::string::ToString;
+    pub use alloc::vec::Vec;
+}
+
+#[cfg(feature = "tokio")]
+pub mod async_io;
 pub mod builder;
+pub mod error;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
 pub mod hdr;
-
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "matrix")]
+pub mod matrix;
+pub mod prelude;
+
+#[cfg(not(feature = "std"))]
+use alloc_prelude::*;
+#[cfg(feature = "std")]
+use builder::{Client, ToRequest};
+#[cfg(feature = "std")]
+pub use error::EncodeError;
+pub use error::{BuildError, DecodeError, InvalidValue, ValidationError};
 use hdr::*;
 
 use self::serde::de;
+use std::cmp;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
 use std::fmt;
-
-use serde::de::{DeserializeOwned, SeqAccess, Visitor};
-use serde::ser::SerializeStruct;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::iter;
+#[cfg(feature = "std")]
+use std::net::SocketAddrV4;
+use std::ops::Range;
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+
+#[cfg(feature = "std")]
+use serde::de::DeserializeOwned;
+use serde::de::{DeserializeSeed, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::error::Error;
 
 // ----------------------------------------------------------------------------
 // Macros.
@@ -172,7 +238,7 @@ macro_rules! def_is_hdr {
 macro_rules! def_get_hdr {
     ($enum_name: ident,
      $variant: ident,
-     $return: ident,
+     $return: ty,
      $fn_name: ident,
      $str_name: expr) => {
         pub fn $fn_name(self) -> $return {
@@ -205,13 +271,304 @@ macro_rules! de_hdr {
 #[derive(Debug, PartialEq)]
 pub struct Request {
     pub cqc_hdr: CqcHdr,
-    pub req_cmd: Option<ReqCmd>,
+    pub body: ReqBody,
 }
 
 impl Request {
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u32 {
+        CqcHdr::hdr_len() + self.body.len()
+    }
+
+    /// The message type in the CQC Header.
+    #[inline]
+    pub fn msg_type(&self) -> MsgType {
+        self.cqc_hdr.msg_type
+    }
+
+    /// Check that the length in the CQC Header matches the body and that
+    /// every command in the body is valid.  In particular every command must
+    /// carry exactly the extra header its instruction requires, so that for
+    /// example an X command with a Rotation Header is rejected.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let actual = self.len() - CqcHdr::hdr_len();
+        if self.cqc_hdr.length != actual {
+            return Err(ValidationError::LengthMismatch {
+                declared: self.cqc_hdr.length,
+                actual,
+            });
+        }
+
+        self.body.validate_cmds(false)
+    }
+
+    /// The byte range occupied by each command, including its extra header,
+    /// within the encoded packet.  Commands are listed in the order in which
+    /// they are encoded.
+    pub fn command_spans(&self) -> Vec<Range<usize>> {
+        let mut spans = Vec::new();
+        self.body.cmd_spans(CqcHdr::hdr_len() as usize, &mut spans);
+        spans
+    }
+
+    /// The kind of every header in the packet in the order in which they are
+    /// encoded.
+    pub fn headers(&self) -> Vec<HeaderKind> {
+        let mut kinds = vec![HeaderKind::Cqc];
+        self.body.hdr_kinds(&mut kinds);
+        kinds
+    }
+
+    /// Compare two requests field by field.  Commands are compared in the
+    /// order in which they appear in the packet.
+    ///
+    /// Returns the fields that differ, or an empty vector if the requests are
+    /// equal.
+    pub fn diff(&self, other: &Request) -> Vec<FieldDiff> {
+        let mut diffs = Vec::new();
+
+        let (lhs, rhs) = (&self.cqc_hdr, &other.cqc_hdr);
+        if lhs.version != rhs.version {
+            diffs.push(FieldDiff::Version);
+        }
+        if lhs.msg_type != rhs.msg_type {
+            diffs.push(FieldDiff::MsgType);
+        }
+        if lhs.app_id != rhs.app_id {
+            diffs.push(FieldDiff::AppId);
+        }
+        if lhs.length != rhs.length {
+            diffs.push(FieldDiff::Length);
+        }
+
+        let (lhs, rhs) = (self.body.cmds(), other.body.cmds());
+        if lhs.len() != rhs.len() {
+            diffs.push(FieldDiff::Body);
+            return diffs;
+        }
+
+        let mut cmds_differ = false;
+        for (index, (lhs, rhs)) in lhs.iter().zip(rhs.iter()).enumerate() {
+            let (lhs_hdr, rhs_hdr) = (&lhs.cmd_hdr, &rhs.cmd_hdr);
+            let fields = [
+                (lhs_hdr.instr != rhs_hdr.instr, FieldDiff::Instr(index)),
+                (
+                    lhs_hdr.qubit_id != rhs_hdr.qubit_id,
+                    FieldDiff::QubitId(index),
+                ),
+                (
+                    lhs_hdr.options != rhs_hdr.options,
+                    FieldDiff::Options(index),
+                ),
+                (lhs.xtra_hdr != rhs.xtra_hdr, FieldDiff::Xtra(index)),
+            ];
+            for (differs, field) in fields.iter() {
+                if *differs {
+                    cmds_differ = true;
+                    diffs.push(*field);
+                }
+            }
+        }
+
+        // The commands match, but the headers around them do not.
+        if !cmds_differ && self.body != other.body {
+            diffs.push(FieldDiff::Body);
+        }
+
+        diffs
+    }
+}
+
+/// # Field Difference
+///
+/// A field that differs between two requests as reported by `Request::diff`.
+/// Command fields carry the index of the command within the request.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FieldDiff {
+    Version,
+    MsgType,
+    AppId,
+    Length,
+    Instr(usize),
+    QubitId(usize),
+    Options(usize),
+    Xtra(usize),
+    /// The bodies differ in structure or in a header other than a command.
+    Body,
+}
+
+/// # Header Kind
+///
+/// The kind of a header as reported by `Request::headers` and
+/// `Response::headers`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HeaderKind {
+    Cqc,
+    Cmd,
+    Rot,
+    Qubit,
+    Comm,
+    Assign,
+    Seq,
+    Factory,
+    If,
+    Type,
+    MeasOut,
+    TimeInfo,
+    EntInfo,
+    /// A response body that was not recognised for its message type.
+    Raw,
+}
+
+/// # Request Body
+///
+/// The headers that follow the CQC Header in a request.  Which variant is
+/// present depends on the message type.
+#[derive(Debug, PartialEq)]
+pub enum ReqBody {
+    Cmd(Vec<ReqCmd>),
+    Factory(FactoryReq),
+    If(IfReq),
+    Mix(Vec<MixSeg>),
+    None,
+}
+
+impl ReqBody {
+    #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> u32 {
-        CqcHdr::hdr_len()
-            + self.req_cmd.as_ref().map(|hdr| hdr.len()).unwrap_or(0)
+        match *self {
+            ReqBody::Cmd(ref cmds) => cmds.iter().map(ReqCmd::len).sum(),
+            ReqBody::Factory(ref factory_req) => factory_req.len(),
+            ReqBody::If(ref if_req) => if_req.len(),
+            ReqBody::Mix(ref segs) => segs.iter().map(MixSeg::len).sum(),
+            ReqBody::None => 0,
+        }
+    }
+
+    def_is_hdr!(ReqBody, Cmd, is_cmd);
+    def_is_hdr!(ReqBody, Factory, is_factory);
+    def_is_hdr!(ReqBody, If, is_if);
+    def_is_hdr!(ReqBody, Mix, is_mix);
+
+    def_get_hdr!(ReqBody, Cmd, Vec<ReqCmd>, get_cmd, "ReqCmd");
+    def_get_hdr!(ReqBody, Factory, FactoryReq, get_factory, "FactoryReq");
+    def_get_hdr!(ReqBody, If, IfReq, get_if, "IfReq");
+    def_get_hdr!(ReqBody, Mix, Vec<MixSeg>, get_mix, "MixSeg");
+
+    /// Validate every command in the body.  Commands in a Mix program are
+    /// validated with `in_mix` set.
+    fn validate_cmds(&self, in_mix: bool) -> Result<(), ValidationError> {
+        match *self {
+            ReqBody::Cmd(ref cmds) => {
+                for req_cmd in cmds {
+                    req_cmd.validate_in(in_mix)?;
+                }
+            }
+            ReqBody::Factory(ref factory_req) => {
+                factory_req.req_cmd.validate_in(in_mix)?
+            }
+            ReqBody::If(ref if_req) => if_req.req_cmd.validate_in(in_mix)?,
+            ReqBody::Mix(ref segs) => {
+                for seg in segs {
+                    seg.body.validate_cmds(true)?;
+                }
+            }
+            ReqBody::None => (),
+        }
+
+        Ok(())
+    }
+
+    /// All the commands in the body in the order in which they are encoded.
+    fn cmds(&self) -> Vec<&ReqCmd> {
+        match *self {
+            ReqBody::Cmd(ref cmds) => cmds.iter().collect(),
+            ReqBody::Factory(ref factory_req) => vec![&factory_req.req_cmd],
+            ReqBody::If(ref if_req) => vec![&if_req.req_cmd],
+            ReqBody::Mix(ref segs) => {
+                segs.iter().flat_map(|seg| seg.body.cmds()).collect()
+            }
+            ReqBody::None => Vec::new(),
+        }
+    }
+
+    /// Append the kind of every header in the body in encoding order.
+    fn hdr_kinds(&self, kinds: &mut Vec<HeaderKind>) {
+        match *self {
+            ReqBody::Cmd(ref cmds) => {
+                for req_cmd in cmds {
+                    req_cmd.hdr_kinds(kinds);
+                }
+            }
+            ReqBody::Factory(ref factory_req) => {
+                kinds.push(HeaderKind::Factory);
+                factory_req.req_cmd.hdr_kinds(kinds);
+            }
+            ReqBody::If(ref if_req) => {
+                kinds.push(HeaderKind::If);
+                if_req.req_cmd.hdr_kinds(kinds);
+            }
+            ReqBody::Mix(ref segs) => {
+                for seg in segs {
+                    kinds.push(HeaderKind::Type);
+                    seg.body.hdr_kinds(kinds);
+                }
+            }
+            ReqBody::None => (),
+        }
+    }
+
+    /// Append the byte range of each command in the body, assuming the body
+    /// starts at `offset`.  Returns the offset of the end of the body.
+    fn cmd_spans(
+        &self,
+        offset: usize,
+        spans: &mut Vec<Range<usize>>,
+    ) -> usize {
+        let cmd_span = |req_cmd: &ReqCmd, start: usize| {
+            start..start + req_cmd.len() as usize
+        };
+
+        match *self {
+            ReqBody::Cmd(ref cmds) => {
+                let mut offset = offset;
+                for req_cmd in cmds {
+                    let span = cmd_span(req_cmd, offset);
+                    offset = span.end;
+                    spans.push(span);
+                }
+                offset
+            }
+            ReqBody::Factory(ref factory_req) => {
+                let start = offset + FactoryHdr::hdr_len() as usize;
+                let span = cmd_span(&factory_req.req_cmd, start);
+                let end = span.end;
+                spans.push(span);
+                end
+            }
+            ReqBody::If(ref if_req) => {
+                let start = offset + IfHdr::hdr_len() as usize;
+                let span = cmd_span(&if_req.req_cmd, start);
+                let end = span.end;
+                spans.push(span);
+                end
+            }
+            ReqBody::Mix(ref segs) => {
+                segs.iter().fold(offset, |offset, seg| {
+                    let start = offset + TypeHdr::hdr_len() as usize;
+                    seg.body.cmd_spans(start, spans)
+                })
+            }
+            ReqBody::None => offset,
+        }
+    }
+
+    pub fn is_some(&self) -> bool {
+        !matches!(*self, ReqBody::None)
+    }
+
+    pub fn is_none(&self) -> bool {
+        matches!(*self, ReqBody::None)
     }
 }
 
@@ -227,28 +584,166 @@ pub struct ReqCmd {
 }
 
 impl ReqCmd {
+    #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> u32 {
         CmdHdr::hdr_len() + self.xtra_hdr.len()
     }
+
+    /// Check that the extra header is the one required by the instruction
+    /// and that two-qubit gates act on two distinct qubits.  Instructions
+    /// that take no extra header may carry a Sequence Header instead if the
+    /// ACTION option is set.
+    ///
+    /// The command is checked as a command outside a Mix program, so a
+    /// measurement must not carry an Assign Header.  `Request::validate`
+    /// checks the commands of a Mix program, where a measurement must carry
+    /// one, accordingly.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        self.validate_in(false)
+    }
+
+    /// Check whether this is a measurement without the Assign Header it
+    /// needs in a Mix program.
+    fn lacks_assign_hdr(&self) -> bool {
+        match self.cmd_hdr.instr {
+            Cmd::Measure | Cmd::MeasureInplace => {
+                !self.xtra_hdr.is_assign_hdr()
+            }
+            _ => false,
+        }
+    }
+
+    /// Validate the command as part of a Mix program if `in_mix` is set.
+    /// This mirrors the decoder which only reads the Assign Header of a
+    /// measurement in a Mix program.
+    fn validate_in(&self, in_mix: bool) -> Result<(), ValidationError> {
+        let instr = self.cmd_hdr.instr;
+        let seq =
+            self.cmd_hdr.options.get_action() && self.xtra_hdr.is_seq_hdr();
+        let valid = match instr {
+            Cmd::RotX | Cmd::RotY | Cmd::RotZ => self.xtra_hdr.is_rot_hdr(),
+            Cmd::Cnot | Cmd::Cphase => self.xtra_hdr.is_qubit_hdr(),
+            Cmd::Send | Cmd::Epr => self.xtra_hdr.is_comm_hdr(),
+            Cmd::Measure | Cmd::MeasureInplace if in_mix => {
+                self.xtra_hdr.is_assign_hdr()
+            }
+            _ => self.xtra_hdr.is_none() || seq,
+        };
+
+        if !valid {
+            return Err(ValidationError::XtraHdrMismatch(instr));
+        }
+
+        if let XtraHdr::Qubit(ref hdr) = self.xtra_hdr {
+            if hdr.qubit_id == self.cmd_hdr.qubit_id {
+                return Err(ValidationError::TargetIsControl(
+                    instr,
+                    hdr.qubit_id,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Append the kinds of the Command Header and the extra header.
+    fn hdr_kinds(&self, kinds: &mut Vec<HeaderKind>) {
+        kinds.push(HeaderKind::Cmd);
+        match self.xtra_hdr {
+            XtraHdr::Rot(_) => kinds.push(HeaderKind::Rot),
+            XtraHdr::Qubit(_) => kinds.push(HeaderKind::Qubit),
+            XtraHdr::Comm(_) => kinds.push(HeaderKind::Comm),
+            XtraHdr::Assign(_) => kinds.push(HeaderKind::Assign),
+            XtraHdr::Seq(_) => kinds.push(HeaderKind::Seq),
+            XtraHdr::None => (),
+        }
+    }
+}
+
+/// # Factory Request
+///
+/// A factory request follows the CQC Header for Factory messages.  It
+/// consists of the Factory Header followed by the command to be repeated.
+#[derive(Debug, PartialEq)]
+pub struct FactoryReq {
+    pub factory_hdr: FactoryHdr,
+    pub req_cmd: ReqCmd,
+}
+
+impl FactoryReq {
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u32 {
+        FactoryHdr::hdr_len() + self.req_cmd.len()
+    }
+
+    /// Check that the repeated command is consistent.  The backend executes
+    /// the same command on every iteration so it must be valid on its own.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        self.req_cmd.validate()
+    }
+}
+
+/// # If Request
+///
+/// An if request consists of the If Header followed by the command that is
+/// executed only if the condition holds.  The If Header's length field is the
+/// length of that command.
+#[derive(Debug, PartialEq)]
+pub struct IfReq {
+    pub if_hdr: IfHdr,
+    pub req_cmd: ReqCmd,
+}
+
+impl IfReq {
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u32 {
+        IfHdr::hdr_len() + self.req_cmd.len()
+    }
+}
+
+/// # Mix Segment
+///
+/// A Mix program is a sequence of segments.  Each segment is announced by a
+/// Type Header whose length field is the length of the body that follows.
+/// The body may be a command or an if request, but never another Mix.
+#[derive(Debug, PartialEq)]
+pub struct MixSeg {
+    pub type_hdr: TypeHdr,
+    pub body: ReqBody,
+}
+
+impl MixSeg {
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u32 {
+        TypeHdr::hdr_len() + self.body.len()
+    }
 }
 
 /// # Extra Header
 ///
 /// Some commands require an additional header to follow the Command Header.
+///
+/// The Sequence Header is not announced by the instruction, so the `Decoder`
+/// never produces `XtraHdr::Seq`.
 #[derive(Debug, PartialEq)]
 pub enum XtraHdr {
     Rot(RotHdr),
     Qubit(QubitHdr),
     Comm(CommHdr),
+    Assign(AssignHdr),
+    Seq(SeqHdr),
     None,
 }
 
 impl XtraHdr {
+    #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> u32 {
         match *self {
             XtraHdr::Rot(_) => RotHdr::hdr_len(),
             XtraHdr::Qubit(_) => QubitHdr::hdr_len(),
             XtraHdr::Comm(_) => CommHdr::hdr_len(),
+            XtraHdr::Assign(_) => AssignHdr::hdr_len(),
+            XtraHdr::Seq(_) => SeqHdr::hdr_len(),
             XtraHdr::None => 0,
         }
     }
@@ -256,23 +751,21 @@ impl XtraHdr {
     def_is_hdr!(XtraHdr, Rot, is_rot_hdr);
     def_is_hdr!(XtraHdr, Qubit, is_qubit_hdr);
     def_is_hdr!(XtraHdr, Comm, is_comm_hdr);
+    def_is_hdr!(XtraHdr, Assign, is_assign_hdr);
+    def_is_hdr!(XtraHdr, Seq, is_seq_hdr);
 
     def_get_hdr!(XtraHdr, Rot, RotHdr, get_rot_hdr, "RotHdr");
     def_get_hdr!(XtraHdr, Qubit, QubitHdr, get_qubit_hdr, "QubitHdr");
     def_get_hdr!(XtraHdr, Comm, CommHdr, get_comm_hdr, "CommHdr");
+    def_get_hdr!(XtraHdr, Assign, AssignHdr, get_assign_hdr, "AssignHdr");
+    def_get_hdr!(XtraHdr, Seq, SeqHdr, get_seq_hdr, "SeqHdr");
 
     pub fn is_some(&self) -> bool {
-        match self {
-            &XtraHdr::None => false,
-            _ => true,
-        }
+        !matches!(*self, XtraHdr::None)
     }
 
     pub fn is_none(&self) -> bool {
-        match self {
-            &XtraHdr::None => true,
-            _ => false,
-        }
+        matches!(*self, XtraHdr::None)
     }
 }
 
@@ -280,36 +773,156 @@ impl XtraHdr {
 // Request serialisation.
 // ----------------------------------------------------------------------------
 
-impl Serialize for Request {
-    #[inline]
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+/// Serialisation of a packet part as the flat sequence of headers in which it
+/// is sent.  Binary formats flatten nested values on their own, but
+/// self-describing formats such as JSON must be given the flat sequence to be
+/// able to read it back with the visitors below.
+trait SerializeHdrs {
+    /// The number of elements appended by `serialize_hdrs`.
+    fn hdr_count(&self) -> usize;
+
+    /// Append every header in the order in which it is sent.
+    fn serialize_hdrs<S>(&self, s: &mut S) -> Result<(), S::Error>
     where
-        S: Serializer,
+        S: SerializeTuple;
+}
+
+macro_rules! serialize_hdrs {
+    ($name: ident) => {
+        impl Serialize for $name {
+            #[inline]
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                let mut s = serializer.serialize_tuple(self.hdr_count())?;
+                self.serialize_hdrs(&mut s)?;
+                s.end()
+            }
+        }
+    }
+}
+
+serialize_hdrs!(Request);
+serialize_hdrs!(ReqBody);
+serialize_hdrs!(MixSeg);
+serialize_hdrs!(FactoryReq);
+serialize_hdrs!(IfReq);
+serialize_hdrs!(ReqCmd);
+
+impl SerializeHdrs for Request {
+    fn hdr_count(&self) -> usize {
+        1 + self.body.hdr_count()
+    }
+
+    fn serialize_hdrs<S>(&self, s: &mut S) -> Result<(), S::Error>
+    where
+        S: SerializeTuple,
     {
-        let mut s = serializer.serialize_struct("Request", 2)?;
-        s.serialize_field("CqcHdr", &self.cqc_hdr)?;
-        if self.req_cmd.is_some() {
-            s.serialize_field("ReqCmd", self.req_cmd.as_ref().unwrap())?;
+        s.serialize_element(&self.cqc_hdr)?;
+        self.body.serialize_hdrs(s)
+    }
+}
+
+impl SerializeHdrs for ReqBody {
+    fn hdr_count(&self) -> usize {
+        match *self {
+            ReqBody::Cmd(ref cmds) => cmds.iter().map(|r| r.hdr_count()).sum(),
+            ReqBody::Factory(ref r) => r.hdr_count(),
+            ReqBody::If(ref r) => r.hdr_count(),
+            ReqBody::Mix(ref segs) => segs.iter().map(|g| g.hdr_count()).sum(),
+            ReqBody::None => 0,
         }
-        s.end()
+    }
+
+    fn serialize_hdrs<S>(&self, s: &mut S) -> Result<(), S::Error>
+    where
+        S: SerializeTuple,
+    {
+        match *self {
+            ReqBody::Cmd(ref cmds) => {
+                for r in cmds {
+                    r.serialize_hdrs(s)?;
+                }
+            }
+            ReqBody::Factory(ref r) => r.serialize_hdrs(s)?,
+            ReqBody::If(ref r) => r.serialize_hdrs(s)?,
+            ReqBody::Mix(ref segs) => {
+                for seg in segs {
+                    seg.serialize_hdrs(s)?;
+                }
+            }
+            ReqBody::None => (),
+        };
+        Ok(())
     }
 }
 
-impl Serialize for ReqCmd {
-    #[inline]
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+impl SerializeHdrs for MixSeg {
+    fn hdr_count(&self) -> usize {
+        1 + self.body.hdr_count()
+    }
+
+    fn serialize_hdrs<S>(&self, s: &mut S) -> Result<(), S::Error>
     where
-        S: Serializer,
+        S: SerializeTuple,
+    {
+        s.serialize_element(&self.type_hdr)?;
+        self.body.serialize_hdrs(s)
+    }
+}
+
+impl SerializeHdrs for FactoryReq {
+    fn hdr_count(&self) -> usize {
+        1 + self.req_cmd.hdr_count()
+    }
+
+    fn serialize_hdrs<S>(&self, s: &mut S) -> Result<(), S::Error>
+    where
+        S: SerializeTuple,
+    {
+        s.serialize_element(&self.factory_hdr)?;
+        self.req_cmd.serialize_hdrs(s)
+    }
+}
+
+impl SerializeHdrs for IfReq {
+    fn hdr_count(&self) -> usize {
+        1 + self.req_cmd.hdr_count()
+    }
+
+    fn serialize_hdrs<S>(&self, s: &mut S) -> Result<(), S::Error>
+    where
+        S: SerializeTuple,
+    {
+        s.serialize_element(&self.if_hdr)?;
+        self.req_cmd.serialize_hdrs(s)
+    }
+}
+
+impl SerializeHdrs for ReqCmd {
+    fn hdr_count(&self) -> usize {
+        if self.xtra_hdr.is_some() {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn serialize_hdrs<S>(&self, s: &mut S) -> Result<(), S::Error>
+    where
+        S: SerializeTuple,
     {
-        let mut s = serializer.serialize_struct("ReqCmd", 2)?;
-        s.serialize_field("CmdHdr", &self.cmd_hdr)?;
+        s.serialize_element(&self.cmd_hdr)?;
         match self.xtra_hdr {
-            XtraHdr::Rot(ref h) => s.serialize_field("RotHdr", h)?,
-            XtraHdr::Qubit(ref h) => s.serialize_field("QubtiHdr", h)?,
-            XtraHdr::Comm(ref h) => s.serialize_field("CommHdr", h)?,
+            XtraHdr::Rot(ref h) => s.serialize_element(h)?,
+            XtraHdr::Qubit(ref h) => s.serialize_element(h)?,
+            XtraHdr::Comm(ref h) => s.serialize_element(h)?,
+            XtraHdr::Assign(ref h) => s.serialize_element(h)?,
+            XtraHdr::Seq(ref h) => s.serialize_element(h)?,
             XtraHdr::None => (),
         };
-        s.end()
+        Ok(())
     }
 }
 
@@ -323,9 +936,9 @@ impl<'de> Deserialize<'de> for Request {
     where
         D: Deserializer<'de>,
     {
-        const FIELDS: &'static [&'static str] =
-            &["CqcHdr", "CmdHdr", "XtraHdr"];
-        deserializer.deserialize_struct("Request", FIELDS, RequestVisitor)
+        // A Mix program may contain any number of headers so the visitor,
+        // not the deserializer, decides when the sequence ends.
+        deserializer.deserialize_tuple(usize::MAX, RequestVisitor)
     }
 }
 
@@ -348,13 +961,20 @@ impl<'de> Visitor<'de> for RequestVisitor {
         let (msg_type, length) = (cqc_hdr.msg_type, cqc_hdr.length);
 
         if length == 0 {
+            if has_cmd_body(msg_type) {
+                return Err(de::Error::custom(format!(
+                    "{} message with an empty command body",
+                    msg_type
+                )));
+            }
+
             return Ok(Request {
                 cqc_hdr,
-                req_cmd: None,
+                body: ReqBody::None,
             });
         }
 
-        let req_cmd = match msg_type {
+        let body = match msg_type {
             MsgType::Tp(Tp::Hello) => {
                 return Err(de::Error::invalid_type(
                     de::Unexpected::Other(
@@ -365,43 +985,23 @@ impl<'de> Visitor<'de> for RequestVisitor {
             }
 
             MsgType::Tp(Tp::GetTime) | MsgType::Tp(Tp::Command) => {
-                de_check_len!("CmdHdr", length, CmdHdr::hdr_len());
-                let cmd_hdr: CmdHdr = de_hdr!(seq);
-
-                let length = length - CmdHdr::hdr_len();
-                let xtra_hdr = match cmd_hdr.instr {
-                    Cmd::RotX | Cmd::RotY | Cmd::RotZ => {
-                        de_check_len!("RotHdr", length, RotHdr::hdr_len());
-                        XtraHdr::Rot(de_hdr!(seq))
-                    }
-
-                    Cmd::Cnot | Cmd::Cphase => {
-                        de_check_len!("QubitHdr", length, QubitHdr::hdr_len());
-                        XtraHdr::Qubit(de_hdr!(seq))
-                    }
+                ReqBody::Cmd(de_cmd_list(&mut seq, length, false)?)
+            }
 
-                    Cmd::Send | Cmd::Epr => {
-                        de_check_len!("CommHdr", length, CommHdr::hdr_len());
-                        XtraHdr::Comm(de_hdr!(seq))
-                    }
+            MsgType::Tp(Tp::Factory) => {
+                ReqBody::Factory(de_factory_req(&mut seq, length)?)
+            }
 
-                    _ => XtraHdr::None,
-                };
+            MsgType::Tp(Tp::Mix) => ReqBody::Mix(de_mix(&mut seq, length)?),
 
-                Some(ReqCmd { cmd_hdr, xtra_hdr })
+            MsgType::Tp(Tp::If) => {
+                ReqBody::If(de_if_req(&mut seq, length, false)?)
             }
 
-            MsgType::Tp(Tp::Factory)
-            | MsgType::Tp(Tp::InfTime)
-            | MsgType::Tp(Tp::Mix)
-            | MsgType::Tp(Tp::If) => {
+            MsgType::Tp(Tp::InfTime) => {
                 return Err(de::Error::invalid_type(
                     de::Unexpected::Other(
-                        &vec![
-                            "Deserialise not yet supported for:".to_string(),
-                            msg_type.to_string(),
-                        ]
-                        .join(" "),
+                        "InfTime message, which is the response to GetTime",
                     ),
                     &self,
                 ));
@@ -410,7 +1010,7 @@ impl<'de> Visitor<'de> for RequestVisitor {
             _ => {
                 return Err(de::Error::invalid_type(
                     de::Unexpected::Other(
-                        &vec![
+                        &[
                             "Unexpected message type:".to_string(),
                             msg_type.to_string(),
                         ]
@@ -421,73 +1021,436 @@ impl<'de> Visitor<'de> for RequestVisitor {
             }
         };
 
-        Ok(Request { cqc_hdr, req_cmd })
+        Ok(Request { cqc_hdr, body })
     }
 }
 
-/// # Response
+/// Deserialise a Command Header and the extra header required by its
+/// instruction.  The command may not be longer than `length` bytes.
 ///
-/// If the notify flag is set on a request, the CQC Backend will return a
-/// response.  It begins with a CQC Header followed by either a Notify Header
-/// or an Entanglement Information Header.
-#[derive(Debug, PartialEq)]
-pub struct Response {
-    pub cqc_hdr: CqcHdr,
-    pub notify: RspInfo,
+/// Measurements are followed by an Assign Header only in Mix programs, as
+/// only there can the stored outcome be referenced, so `in_mix` selects
+/// whether one is expected.
+fn de_req_cmd<'de, V>(
+    seq: &mut V,
+    length: u32,
+    in_mix: bool,
+) -> Result<ReqCmd, V::Error>
+where
+    V: SeqAccess<'de>,
+{
+    de_check_len!("CmdHdr", length, CmdHdr::hdr_len());
+    let cmd_hdr: CmdHdr = de_hdr!(seq);
+
+    let length = length - CmdHdr::hdr_len();
+    let xtra_hdr = match cmd_hdr.instr {
+        Cmd::RotX | Cmd::RotY | Cmd::RotZ => {
+            de_check_len!("RotHdr", length, RotHdr::hdr_len());
+            XtraHdr::Rot(de_hdr!(seq))
+        }
+
+        Cmd::Cnot | Cmd::Cphase => {
+            de_check_len!("QubitHdr", length, QubitHdr::hdr_len());
+            XtraHdr::Qubit(de_hdr!(seq))
+        }
+
+        Cmd::Send | Cmd::Epr => {
+            de_check_len!("CommHdr", length, CommHdr::hdr_len());
+            XtraHdr::Comm(de_hdr!(seq))
+        }
+
+        Cmd::Measure | Cmd::MeasureInplace if in_mix => {
+            de_check_len!("AssignHdr", length, AssignHdr::hdr_len());
+            XtraHdr::Assign(de_hdr!(seq))
+        }
+
+        _ => XtraHdr::None,
+    };
+
+    Ok(ReqCmd { cmd_hdr, xtra_hdr })
 }
 
-impl Response {
-    pub fn len(&self) -> u32 {
-        CqcHdr::hdr_len() + self.notify.len()
+/// Deserialise a list of commands which together span exactly `length`
+/// bytes.
+fn de_cmd_list<'de, V>(
+    seq: &mut V,
+    length: u32,
+    in_mix: bool,
+) -> Result<Vec<ReqCmd>, V::Error>
+where
+    V: SeqAccess<'de>,
+{
+    let mut cmds = Vec::new();
+    let mut length = length;
+
+    while length > 0 {
+        let req_cmd = de_req_cmd(seq, length, in_mix)?;
+        length -= req_cmd.len();
+        cmds.push(req_cmd);
     }
+
+    Ok(cmds)
 }
 
-/// # Response Info
-///
-/// Some responses from a CQC backed will be followed by either a Notify Header
-/// or an Entanglement Info Header.
-#[derive(Debug, PartialEq)]
-pub enum RspInfo {
-    Qubit(QubitHdr),
-    MeasOut(MeasOutHdr),
-    Epr(EprInfo),
-    Time(TimeInfoHdr),
-    None,
+/// Deserialise a Factory Header and the command it repeats which together
+/// span exactly `length` bytes.
+fn de_factory_req<'de, V>(
+    seq: &mut V,
+    length: u32,
+) -> Result<FactoryReq, V::Error>
+where
+    V: SeqAccess<'de>,
+{
+    de_check_len!("FactoryHdr", length, FactoryHdr::hdr_len());
+    let factory_hdr: FactoryHdr = de_hdr!(seq);
+    let req_cmd = de_req_cmd(seq, length - FactoryHdr::hdr_len(), false)?;
+
+    let factory_req = FactoryReq {
+        factory_hdr,
+        req_cmd,
+    };
+    if factory_req.len() != length {
+        return Err(de::Error::invalid_length(
+            length as usize,
+            &"a single command following the FactoryHdr",
+        ));
+    }
+
+    Ok(factory_req)
 }
 
-impl RspInfo {
-    pub fn len(&self) -> u32 {
+/// Deserialise an If Header and its conditional command.  The command must
+/// fill the length announced by the If Header and together they must span
+/// exactly `length` bytes.
+fn de_if_req<'de, V>(
+    seq: &mut V,
+    length: u32,
+    in_mix: bool,
+) -> Result<IfReq, V::Error>
+where
+    V: SeqAccess<'de>,
+{
+    de_check_len!("IfHdr", length, IfHdr::hdr_len());
+    let if_hdr: IfHdr = de_hdr!(seq);
+
+    let cmd_len = if_hdr.length;
+    de_check_len!("IfHdr length", length - IfHdr::hdr_len(), cmd_len);
+    let req_cmd = de_req_cmd(seq, cmd_len, in_mix)?;
+    if req_cmd.len() != cmd_len {
+        return Err(de::Error::invalid_length(
+            cmd_len as usize,
+            &"a single command announced by the IfHdr",
+        ));
+    }
+
+    let if_req = IfReq { if_hdr, req_cmd };
+    if if_req.len() != length {
+        return Err(de::Error::invalid_length(
+            length as usize,
+            &"a single command following the IfHdr",
+        ));
+    }
+
+    Ok(if_req)
+}
+
+/// Deserialise the segments of a Mix program which together span exactly
+/// `length` bytes.  Each segment must fill the length announced by its Type
+/// Header.
+fn de_mix<'de, V>(seq: &mut V, length: u32) -> Result<Vec<MixSeg>, V::Error>
+where
+    V: SeqAccess<'de>,
+{
+    let mut segs = Vec::new();
+    let mut length = length;
+
+    while length > 0 {
+        de_check_len!("TypeHdr", length, TypeHdr::hdr_len());
+        let type_hdr: TypeHdr = de_hdr!(seq);
+
+        length -= TypeHdr::hdr_len();
+        de_check_len!("Mix segment", length, type_hdr.length);
+
+        let body = match type_hdr.hdr_type {
+            Tp::Command => {
+                ReqBody::Cmd(de_cmd_list(seq, type_hdr.length, true)?)
+            }
+            Tp::If => ReqBody::If(de_if_req(seq, type_hdr.length, true)?),
+            Tp::Mix => {
+                return Err(de::Error::custom(
+                    "Type Header in a Mix program may not announce Mix",
+                ));
+            }
+            hdr_type => {
+                return Err(de::Error::custom(format!(
+                    "Unsupported header type in Mix program: {}",
+                    hdr_type
+                )));
+            }
+        };
+
+        if body.len() != type_hdr.length {
+            return Err(de::Error::invalid_length(
+                body.len() as usize,
+                &"a body filling the Type Header length",
+            ));
+        }
+
+        length -= type_hdr.length;
+        segs.push(MixSeg { type_hdr, body });
+    }
+
+    Ok(segs)
+}
+
+/// # Response
+///
+/// If the notify flag is set on a request, the CQC Backend will return a
+/// response.  It begins with a CQC Header followed by either a Notify Header
+/// or an Entanglement Information Header.
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub cqc_hdr: CqcHdr,
+    pub notify: RspInfo,
+}
+
+impl Response {
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u32 {
+        CqcHdr::hdr_len() + self.notify.len()
+    }
+
+    /// The message type in the CQC Header.
+    #[inline]
+    pub fn msg_type(&self) -> MsgType {
+        self.cqc_hdr.msg_type
+    }
+
+    /// Whether the backend reported an error.
+    #[inline]
+    pub fn is_error(&self) -> bool {
+        self.cqc_hdr.msg_type.is_err()
+    }
+
+    /// Check that the length in the CQC Header matches the body and that the
+    /// body is the one carried by the message type.  For example an EprOk
+    /// response must carry `RspInfo::Epr`.  Responses nested in a factory
+    /// response are checked as well.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let actual = self.notify.len();
+        if self.cqc_hdr.length != actual {
+            return Err(ValidationError::LengthMismatch {
+                declared: self.cqc_hdr.length,
+                actual,
+            });
+        }
+
+        let msg_type = self.cqc_hdr.msg_type;
+        let valid = matches!(
+            (msg_type, &self.notify),
+            (MsgType::Tp(Tp::Recv), &RspInfo::Qubit(_))
+                | (MsgType::Tp(Tp::NewOk), &RspInfo::Qubit(_))
+                | (MsgType::Tp(Tp::NewOk), &RspInfo::Qubits(_))
+                | (MsgType::Tp(Tp::MeasOut), &RspInfo::MeasOut(_))
+                | (MsgType::Tp(Tp::EprOk), &RspInfo::Epr(_))
+                | (MsgType::Tp(Tp::InfTime), &RspInfo::Time(_))
+                | (MsgType::Tp(Tp::Factory), &RspInfo::Factory(_))
+                | (MsgType::Tp(Tp::Expire), &RspInfo::Qubit(_))
+                | (MsgType::Tp(Tp::Hello), &RspInfo::None)
+                | (MsgType::Tp(Tp::Done), &RspInfo::None)
+                | (MsgType::Err(_), &RspInfo::None)
+                | (MsgType::Err(_), &RspInfo::Raw(_))
+                | (MsgType::Other(_), &RspInfo::None)
+                | (MsgType::Other(_), &RspInfo::Raw(_))
+        );
+
+        if !valid {
+            return Err(ValidationError::NotifyMismatch(msg_type));
+        }
+
+        if let RspInfo::Factory(ref rsps) = self.notify {
+            for rsp in rsps {
+                rsp.validate()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The kind of every header in the packet in the order in which they are
+    /// encoded.
+    pub fn headers(&self) -> Vec<HeaderKind> {
+        let mut kinds = vec![HeaderKind::Cqc];
+        match self.notify {
+            RspInfo::Qubit(_) => kinds.push(HeaderKind::Qubit),
+            RspInfo::Qubits(ref hdrs) => {
+                kinds.extend(hdrs.iter().map(|_| HeaderKind::Qubit))
+            }
+            RspInfo::MeasOut(_) => kinds.push(HeaderKind::MeasOut),
+            RspInfo::Epr(_) => {
+                kinds.push(HeaderKind::Qubit);
+                kinds.push(HeaderKind::EntInfo);
+            }
+            RspInfo::Time(_) => kinds.push(HeaderKind::TimeInfo),
+            RspInfo::Factory(ref rsps) => {
+                for rsp in rsps {
+                    kinds.extend(rsp.headers());
+                }
+            }
+            RspInfo::Raw(_) => kinds.push(HeaderKind::Raw),
+            RspInfo::None => (),
+        }
+        kinds
+    }
+
+    /// The outcome of a measurement response.
+    ///
+    /// Returns an error naming the actual message type if this is not a
+    /// MeasOut response.
+    pub fn measurement(self) -> Result<MeasOut, DecodeError> {
+        match self.notify {
+            RspInfo::MeasOut(meas_out_hdr) => Ok(meas_out_hdr.meas_out),
+            _ => Err(DecodeError::UnexpectedResponse {
+                expected: MsgType::Tp(Tp::MeasOut),
+                actual: self.cqc_hdr.msg_type,
+            }),
+        }
+    }
+
+    /// The command named by an Unsupp error response built with
+    /// `builder::Server::unsupported`.
+    ///
+    /// Returns `None` for other responses and for Unsupp responses without
+    /// the non-standard single byte body.
+    pub fn unsupported_command(&self) -> Option<Cmd> {
+        if self.cqc_hdr.msg_type != MsgType::Err(Err::Unsupp) {
+            return None;
+        }
+
+        match self.notify {
+            RspInfo::Raw(ref bytes) if bytes.len() == 1 => Cmd::get(bytes[0]),
+            _ => None,
+        }
+    }
+
+    /// Check whether this response is a Hello message acknowledging a
+    /// liveness check.
+    #[inline]
+    pub fn is_hello(&self) -> bool {
+        self.cqc_hdr.msg_type == MsgType::Tp(Tp::Hello)
+    }
+
+    /// Recover the correlation token of the request this response answers,
+    /// assuming the top `token_bits` bits of the application ID hold the
+    /// token as set up by `builder::Client::with_correlation`.
+    ///
+    /// Returns `None` if `token_bits` is 0 or 16 or more.
+    pub fn correlation(&self, token_bits: u8) -> Option<u16> {
+        if token_bits == 0 || token_bits >= 16 {
+            return None;
+        }
+
+        Some(self.cqc_hdr.app_id >> (16 - token_bits))
+    }
+
+    /// Describe the entanglement reported by an EPR response in a
+    /// human-readable form for logging.
+    ///
+    /// The IPv4 addresses of both nodes are reverse-resolved to hostnames.
+    /// If resolution fails the dotted-quad form of the address is used
+    /// instead.  Requires the `resolve` feature.
+    #[cfg(feature = "resolve")]
+    pub fn describe_entanglement(&self) -> String {
+        let epr = match self.notify {
+            RspInfo::Epr(ref epr) => epr,
+            _ => {
+                return format!(
+                    "{} response without entanglement information",
+                    self.cqc_hdr.msg_type
+                )
+            }
+        };
+
+        let resolve = |node: u32| {
+            let addr = std::net::IpAddr::from(std::net::Ipv4Addr::from(node));
+            dns_lookup::lookup_addr(&addr).unwrap_or_else(|_| addr.to_string())
+        };
+
+        let ent = &epr.ent_info_hdr;
+        format!(
+            "qubit {} entanglement {} between {}:{} (app {}) and {}:{} \
+             (app {}), goodness {}",
+            epr.qubit_hdr.qubit_id,
+            ent.id_ab,
+            resolve(ent.node_a),
+            ent.port_a,
+            ent.app_id_a,
+            resolve(ent.node_b),
+            ent.port_b,
+            ent.app_id_b,
+            ent.goodness
+        )
+    }
+}
+
+/// # Response Info
+///
+/// Some responses from a CQC backed will be followed by either a Notify Header
+/// or an Entanglement Info Header.  A NewOk response to an allocation of
+/// several qubits carries one Extra Qubit Header per qubit.  The result of a
+/// factory is a sequence of complete responses, one per iteration.  A body
+/// that is not recognised for the message type is kept as raw bytes.
+#[derive(Debug, PartialEq)]
+pub enum RspInfo {
+    Qubit(QubitHdr),
+    Qubits(Vec<QubitHdr>),
+    MeasOut(MeasOutHdr),
+    Epr(EprInfo),
+    Time(TimeInfoHdr),
+    Factory(Vec<Response>),
+    Raw(Vec<u8>),
+    None,
+}
+
+impl RspInfo {
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u32 {
         match *self {
             RspInfo::Qubit(_) => QubitHdr::hdr_len(),
+            RspInfo::Qubits(ref hdrs) => {
+                hdrs.len() as u32 * QubitHdr::hdr_len()
+            }
             RspInfo::MeasOut(_) => MeasOutHdr::hdr_len(),
             RspInfo::Epr(_) => QubitHdr::hdr_len() + EntInfoHdr::hdr_len(),
             RspInfo::Time(_) => TimeInfoHdr::hdr_len(),
+            RspInfo::Factory(ref rsps) => rsps.iter().map(Response::len).sum(),
+            RspInfo::Raw(ref bytes) => bytes.len() as u32,
             RspInfo::None => 0,
         }
     }
 
     def_is_hdr!(RspInfo, Qubit, is_qubit_hdr);
+    def_is_hdr!(RspInfo, Qubits, is_qubits);
     def_is_hdr!(RspInfo, MeasOut, is_meas_out_hdr);
     def_is_hdr!(RspInfo, Epr, is_epr_hdr);
     def_is_hdr!(RspInfo, Time, is_time_info_hdr);
+    def_is_hdr!(RspInfo, Factory, is_factory);
+    def_is_hdr!(RspInfo, Raw, is_raw);
 
     def_get_hdr!(RspInfo, Qubit, QubitHdr, get_qubit_hdr, "QubitHdr");
+    def_get_hdr!(RspInfo, Qubits, Vec<QubitHdr>, get_qubits, "Qubits");
     def_get_hdr!(RspInfo, MeasOut, MeasOutHdr, get_meas_out_hdr, "MeasOutHdr");
     def_get_hdr!(RspInfo, Epr, EprInfo, get_epr_hdr, "EprInfo");
     def_get_hdr!(RspInfo, Time, TimeInfoHdr, get_time_info_hdr, "TimeInfoHdr");
+    def_get_hdr!(RspInfo, Factory, Vec<Response>, get_factory, "Factory");
+    def_get_hdr!(RspInfo, Raw, Vec<u8>, get_raw, "Raw");
 
     pub fn is_some(&self) -> bool {
-        match self {
-            &RspInfo::None => false,
-            _ => true,
-        }
+        !matches!(*self, RspInfo::None)
     }
 
     pub fn is_none(&self) -> bool {
-        match self {
-            &RspInfo::None => true,
-            _ => false,
-        }
+        matches!(*self, RspInfo::None)
     }
 }
 
@@ -505,35 +1468,86 @@ pub struct EprInfo {
 // Response serialisation.
 // ----------------------------------------------------------------------------
 
-impl Serialize for Response {
+serialize_hdrs!(Response);
+serialize_hdrs!(RspInfo);
+
+impl SerializeHdrs for Response {
+    fn hdr_count(&self) -> usize {
+        1 + self.notify.hdr_count()
+    }
+
+    fn serialize_hdrs<S>(&self, s: &mut S) -> Result<(), S::Error>
+    where
+        S: SerializeTuple,
+    {
+        s.serialize_element(&self.cqc_hdr)?;
+        self.notify.serialize_hdrs(s)
+    }
+}
+
+impl SerializeHdrs for RspInfo {
+    fn hdr_count(&self) -> usize {
+        if self.is_some() {
+            1
+        } else {
+            0
+        }
+    }
+
+    fn serialize_hdrs<S>(&self, s: &mut S) -> Result<(), S::Error>
+    where
+        S: SerializeTuple,
+    {
+        match self {
+            RspInfo::Qubit(ref h) => s.serialize_element(h)?,
+            RspInfo::Qubits(ref h) => s.serialize_element(&Elements(h))?,
+            RspInfo::MeasOut(ref h) => s.serialize_element(h)?,
+            RspInfo::Epr(ref h) => s.serialize_element(h)?,
+            RspInfo::Time(ref h) => s.serialize_element(h)?,
+            RspInfo::Factory(ref r) => s.serialize_element(&Elements(r))?,
+            RspInfo::Raw(ref b) => s.serialize_element(&RawBytes(b))?,
+            RspInfo::None => (),
+        };
+        Ok(())
+    }
+}
+
+/// Several headers or responses that form a single element, so that the
+/// sequence they belong to has a fixed length.  The elements are read back by
+/// `QubitHdrsSeed` or `FactoryRspSeed`.
+struct Elements<'a, T: 'a>(&'a [T]);
+
+impl<'a, T> Serialize for Elements<'a, T>
+where
+    T: Serialize,
+{
     #[inline]
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        let mut s = serializer.serialize_struct("Response", 2)?;
-        s.serialize_field("CqcHdr", &self.cqc_hdr)?;
-        if self.notify.is_some() {
-            s.serialize_field("RspInfo", &self.notify)?;
+        let mut s = serializer.serialize_tuple(self.0.len())?;
+        for elem in self.0 {
+            s.serialize_element(elem)?;
         }
         s.end()
     }
 }
 
-impl Serialize for RspInfo {
+/// A raw message body.  Unlike a byte slice it is serialised without a
+/// length prefix as the length is already given by the CQC Header.
+struct RawBytes<'a>(&'a [u8]);
+
+impl<'a> Serialize for RawBytes<'a> {
     #[inline]
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        let mut s = serializer.serialize_struct("RspInfo", 1)?;
-        match self {
-            RspInfo::Qubit(ref h) => s.serialize_field("QubtiHdr", h)?,
-            RspInfo::MeasOut(ref h) => s.serialize_field("MeasOutHdr", h)?,
-            RspInfo::Epr(ref h) => s.serialize_field("EprInfo", h)?,
-            RspInfo::Time(ref h) => s.serialize_field("TimeInfoHdr", h)?,
-            RspInfo::None => (),
-        };
+        let mut s = serializer.serialize_tuple(self.0.len())?;
+        for byte in self.0 {
+            s.serialize_element(byte)?;
+        }
         s.end()
     }
 }
@@ -548,12 +1562,26 @@ impl<'de> Deserialize<'de> for Response {
     where
         D: Deserializer<'de>,
     {
-        const FIELDS: &'static [&'static str] = &["CqcHdr", "Notify"];
-        deserializer.deserialize_struct("Response", FIELDS, ResponseVisitor)
+        ResponseVisitor(None).deserialize(deserializer)
     }
 }
 
-struct ResponseVisitor;
+/// Deserialise a response.  A response nested in a factory response may
+/// occupy at most the given number of bytes.
+struct ResponseVisitor(Option<u32>);
+
+impl<'de> DeserializeSeed<'de> for ResponseVisitor {
+    type Value = Response;
+
+    #[inline]
+    fn deserialize<D>(self, deserializer: D) -> Result<Response, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        const FIELDS: &[&str] = &["CqcHdr", "Notify"];
+        deserializer.deserialize_struct("Response", FIELDS, self)
+    }
+}
 
 impl<'de> Visitor<'de> for ResponseVisitor {
     type Value = Response;
@@ -571,107 +1599,1180 @@ impl<'de> Visitor<'de> for ResponseVisitor {
         let cqc_hdr: CqcHdr = de_hdr!(seq);
         let (msg_type, length) = (cqc_hdr.msg_type, cqc_hdr.length);
 
-        if length == 0 {
-            return Ok(Response {
-                cqc_hdr,
-                notify: RspInfo::None,
-            });
+        // Check the length before the body so that a corrupt length is not
+        // used to read, or allocate, past the end of the factory response.
+        if let Some(limit) = self.0 {
+            let len = u64::from(CqcHdr::hdr_len()) + u64::from(length);
+            if len > u64::from(limit) {
+                return Err(de::Error::invalid_length(
+                    len as usize,
+                    &"a response within the factory length",
+                ));
+            }
         }
 
-        let notify = match msg_type {
-            MsgType::Tp(Tp::Recv) | MsgType::Tp(Tp::NewOk) => {
-                de_check_len!("QubitHdr", length, QubitHdr::hdr_len());
-                RspInfo::Qubit(de_hdr!(seq))
-            }
+        let notify = de_rsp_info(&mut seq, msg_type, length)?;
 
-            MsgType::Tp(Tp::MeasOut) => {
-                de_check_len!("MeasOutHdr", length, MeasOutHdr::hdr_len());
-                RspInfo::MeasOut(de_hdr!(seq))
-            }
+        Ok(Response { cqc_hdr, notify })
+    }
+}
 
-            MsgType::Tp(Tp::InfTime) => {
-                de_check_len!("TimeInfoHdr", length, TimeInfoHdr::hdr_len());
-                RspInfo::Time(de_hdr!(seq))
+/// Deserialise the body of a response of the given message type that is
+/// `length` bytes long.
+#[allow(clippy::manual_is_multiple_of)]
+fn de_rsp_info<'de, V>(
+    seq: &mut V,
+    msg_type: MsgType,
+    length: u32,
+) -> Result<RspInfo, V::Error>
+where
+    V: SeqAccess<'de>,
+{
+    if length == 0 {
+        return Ok(RspInfo::None);
+    }
+
+    let notify = match msg_type {
+        // The allocation of several qubits is answered with one Qubit Header
+        // per qubit so the body must hold a whole number of them.
+        MsgType::Tp(Tp::NewOk) => {
+            if length % QubitHdr::hdr_len() != 0 {
+                return Err(de::Error::invalid_length(
+                    length as usize,
+                    &"QubitHdr",
+                ));
             }
 
-            MsgType::Tp(Tp::EprOk) => {
-                de_check_len!(
-                    "QubitHdr + EntInfoHdr",
-                    length,
-                    QubitHdr::hdr_len() + EntInfoHdr::hdr_len()
-                );
-                RspInfo::Epr(de_hdr!(seq))
+            let count = length / QubitHdr::hdr_len();
+            if count == 1 {
+                RspInfo::Qubit(de_hdr!(seq))
+            } else {
+                RspInfo::Qubits(
+                    seq.next_element_seed(QubitHdrsSeed(count))?.unwrap(),
+                )
             }
+        }
 
-            _ => RspInfo::None,
-        };
+        MsgType::Tp(Tp::Recv) | MsgType::Tp(Tp::Expire) => {
+            de_check_len!("QubitHdr", length, QubitHdr::hdr_len());
+            RspInfo::Qubit(de_hdr!(seq))
+        }
 
-        Ok(Response { cqc_hdr, notify })
-    }
+        MsgType::Tp(Tp::MeasOut) => {
+            de_check_len!("MeasOutHdr", length, MeasOutHdr::hdr_len());
+            RspInfo::MeasOut(de_hdr!(seq))
+        }
+
+        MsgType::Tp(Tp::InfTime) => {
+            de_check_len!("TimeInfoHdr", length, TimeInfoHdr::hdr_len());
+            RspInfo::Time(de_hdr!(seq))
+        }
+
+        MsgType::Tp(Tp::EprOk) => {
+            de_check_len!(
+                "QubitHdr + EntInfoHdr",
+                length,
+                QubitHdr::hdr_len() + EntInfoHdr::hdr_len()
+            );
+            RspInfo::Epr(de_hdr!(seq))
+        }
+
+        MsgType::Tp(Tp::Factory) => RspInfo::Factory(
+            seq.next_element_seed(FactoryRspSeed(length))?.unwrap(),
+        ),
+
+        _ => {
+            RspInfo::Raw(seq.next_element_seed(RawBytesSeed(length))?.unwrap())
+        }
+    };
+
+    Ok(notify)
 }
 
-/// # Packet encoder
-///
-/// A basic packet encoder
-pub struct Encoder {
-    config: bincode::Config,
+/// Check whether `de_rsp_info` keeps the body of responses of this type as
+/// raw bytes.
+#[cfg(feature = "std")]
+fn has_raw_body(msg_type: MsgType) -> bool {
+    !matches!(
+        msg_type,
+        MsgType::Tp(Tp::NewOk)
+            | MsgType::Tp(Tp::Recv)
+            | MsgType::Tp(Tp::Expire)
+            | MsgType::Tp(Tp::MeasOut)
+            | MsgType::Tp(Tp::InfTime)
+            | MsgType::Tp(Tp::EprOk)
+            | MsgType::Tp(Tp::Factory)
+    )
 }
 
-impl Encoder {
-    /// Create a big endian `Encoder`.
-    pub fn new() -> Encoder {
-        let mut config = bincode::config();
-        config.big_endian();
+/// Deserialise the body of a response whose CQC Header has already been
+/// decoded.
+#[cfg(feature = "std")]
+struct RspInfoSeed<'a>(&'a CqcHdr);
 
-        Encoder { config }
+#[cfg(feature = "std")]
+impl<'de, 'a> DeserializeSeed<'de> for RspInfoSeed<'a> {
+    type Value = RspInfo;
+
+    #[inline]
+    fn deserialize<D>(self, deserializer: D) -> Result<RspInfo, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_tuple(1, self)
     }
+}
 
-    /// Encode a CQC request packet into buffer of bytes.
-    ///
-    /// If the provided buffer is not large enough to encode the request
-    /// `encode_request` will panic.
-    pub fn encode<'buf, T>(&self, request: &T, buffer: &'buf mut [u8])
+#[cfg(feature = "std")]
+impl<'de, 'a> Visitor<'de> for RspInfoSeed<'a> {
+    type Value = RspInfo;
+
+    #[inline]
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a {} response body", self.0.msg_type)
+    }
+
+    #[inline]
+    fn visit_seq<V>(self, mut seq: V) -> Result<RspInfo, V::Error>
     where
-        T: Serialize,
+        V: SeqAccess<'de>,
     {
-        self.config
-            .serialize_into(&mut buffer[..], &request)
-            .unwrap();
+        de_rsp_info(&mut seq, self.0.msg_type, self.0.length)
     }
+}
 
-    /// Encode a CQC request packet into a newly allocated vector of bytes.
-    pub fn into_vec<T>(&self, request: &T) -> Vec<u8>
+/// Deserialise the given number of Extra Qubit Headers.
+struct QubitHdrsSeed(u32);
+
+impl<'de> DeserializeSeed<'de> for QubitHdrsSeed {
+    type Value = Vec<QubitHdr>;
+
+    #[inline]
+    fn deserialize<D>(self, deserializer: D) -> Result<Vec<QubitHdr>, D::Error>
     where
-        T: Serialize,
+        D: Deserializer<'de>,
     {
-        self.config.serialize(&request).unwrap()
+        deserializer.deserialize_tuple(self.0 as usize, self)
     }
 }
 
-/// # Packet decoder
-///
-/// A basic packet decoder.
-pub struct Decoder {
-    config: bincode::Config,
+impl<'de> Visitor<'de> for QubitHdrsSeed {
+    type Value = Vec<QubitHdr>;
+
+    #[inline]
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{} Extra Qubit Headers", self.0)
+    }
+
+    #[inline]
+    fn visit_seq<V>(self, mut seq: V) -> Result<Vec<QubitHdr>, V::Error>
+    where
+        V: SeqAccess<'de>,
+    {
+        let mut hdrs = Vec::with_capacity(self.0 as usize);
+        for _ in 0..self.0 {
+            hdrs.push(de_hdr!(seq));
+        }
+        Ok(hdrs)
+    }
 }
 
-impl Decoder {
-    /// Create a big endian `Decoder`.
-    pub fn new() -> Decoder {
-        let mut config = bincode::config();
-        config.big_endian();
+/// Deserialise the per-iteration responses of a factory.  Responses are read
+/// until the `length` bytes given by the CQC Header have been consumed.
+struct FactoryRspSeed(u32);
+
+impl<'de> DeserializeSeed<'de> for FactoryRspSeed {
+    type Value = Vec<Response>;
 
-        Decoder { config }
+    #[inline]
+    fn deserialize<D>(self, deserializer: D) -> Result<Vec<Response>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // The number of responses is not known up front so the visitor, not
+        // the deserializer, decides when the sequence ends.
+        deserializer.deserialize_tuple(usize::MAX, self)
     }
+}
 
-    /// Decode supplied data.
-    ///
-    /// Returns a Result which contains either the Response or an error.
-    pub fn decode<T>(&self, buffer: &[u8]) -> Result<T, Box<dyn Error>>
+impl<'de> Visitor<'de> for FactoryRspSeed {
+    type Value = Vec<Response>;
+
+    #[inline]
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "factory responses filling {} bytes", self.0)
+    }
+
+    #[inline]
+    fn visit_seq<V>(self, mut seq: V) -> Result<Vec<Response>, V::Error>
     where
-        T: DeserializeOwned,
+        V: SeqAccess<'de>,
     {
-        let response = self.config.deserialize_from(buffer)?;
-        Ok(response)
+        let mut length = self.0;
+        let mut rsps = Vec::new();
+
+        while length > 0 {
+            let rsp = seq
+                .next_element_seed(ResponseVisitor(Some(length)))?
+                .unwrap();
+            if rsp.len() > length {
+                return Err(de::Error::invalid_length(
+                    rsp.len() as usize,
+                    &"a response within the factory length",
+                ));
+            }
+
+            length -= rsp.len();
+            rsps.push(rsp);
+        }
+
+        Ok(rsps)
     }
 }
+
+/// The largest number of elements reserved up front for a sequence whose
+/// length is taken from a header.
+const MAX_PREALLOC: usize = 4096;
+
+/// Deserialise a message body the crate does not recognise as raw bytes.
+///
+/// This is only used when a response is deserialised through `serde`
+/// directly.  The `Decoder` copies such a body straight out of its buffer.
+struct RawBytesSeed(u32);
+
+impl<'de> DeserializeSeed<'de> for RawBytesSeed {
+    type Value = Vec<u8>;
+
+    #[inline]
+    fn deserialize<D>(self, deserializer: D) -> Result<Vec<u8>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_tuple(self.0 as usize, self)
+    }
+}
+
+impl<'de> Visitor<'de> for RawBytesSeed {
+    type Value = Vec<u8>;
+
+    #[inline]
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a message body of {} bytes", self.0)
+    }
+
+    #[inline]
+    fn visit_seq<V>(self, mut seq: V) -> Result<Vec<u8>, V::Error>
+    where
+        V: SeqAccess<'de>,
+    {
+        // The length has not been checked against the input yet so it is not
+        // trusted to size the vector.
+        let capacity = cmp::min(self.0 as usize, MAX_PREALLOC);
+        let mut bytes = Vec::with_capacity(capacity);
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+
+        if bytes.len() != self.0 as usize {
+            return Err(de::Error::invalid_length(bytes.len(), &self));
+        }
+
+        Ok(bytes)
+    }
+}
+
+/// # Packet encoder
+///
+/// A basic packet encoder
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+pub struct Encoder {
+    config: bincode::Config,
+    comm_hdr_layout: CommHdrLayout,
+}
+
+#[cfg(feature = "std")]
+impl Default for Encoder {
+    fn default() -> Encoder {
+        Encoder::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Encoder {
+    /// Create a big endian `Encoder`.
+    #[allow(deprecated)]
+    pub fn new() -> Encoder {
+        let mut config = bincode::config();
+        config.big_endian();
+
+        Encoder {
+            config,
+            comm_hdr_layout: CommHdrLayout::default(),
+        }
+    }
+
+    /// Create a little endian `Encoder`.
+    ///
+    /// The CQC wire format is big endian, but some local SimulaQron setups
+    /// use the host byte order instead.
+    #[allow(deprecated)]
+    pub fn little_endian() -> Encoder {
+        let mut config = bincode::config();
+        config.little_endian();
+
+        Encoder {
+            config,
+            comm_hdr_layout: CommHdrLayout::default(),
+        }
+    }
+
+    /// Create a little endian `Encoder` matching the default of the decoder
+    /// in earlier versions of this crate.
+    ///
+    /// Earlier versions decoded little endian by default, whereas `new` now
+    /// encodes big endian as CQC requires.  Use this constructor when talking
+    /// to peers that still expect the old default.
+    pub fn legacy_little_endian() -> Encoder {
+        Encoder::little_endian()
+    }
+
+    /// Set the field order used for Communication Headers.  This is only
+    /// needed to talk to backends built against a specification revision
+    /// which orders the fields differently.
+    pub fn comm_hdr_layout(mut self, layout: CommHdrLayout) -> Encoder {
+        self.comm_hdr_layout = layout;
+        self
+    }
+
+    /// The number of bytes `value` occupies once encoded.  Use this to size
+    /// the buffer passed to `encode` for any serializable header, request or
+    /// response.
+    pub fn encoded_len<T>(&self, value: &T) -> u64
+    where
+        T: Serialize,
+    {
+        match self.config.serialized_size(value) {
+            Ok(len) => len,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Encode a CQC request packet into buffer of bytes.
+    ///
+    /// If the provided buffer is not large enough to encode the request
+    /// `encode` will panic.  Use `try_encode` to handle this case instead.
+    pub fn encode<T>(&self, request: &T, buffer: &mut [u8])
+    where
+        T: Serialize,
+    {
+        if let Err(err) = self.try_encode(request, buffer) {
+            panic!("{}", err);
+        }
+    }
+
+    /// Encode a user type that converts into a CQC request into buffer of
+    /// bytes.
+    ///
+    /// Panics under the same conditions as `encode`.
+    pub fn encode_as<T>(&self, value: &T, client: &Client, buffer: &mut [u8])
+    where
+        T: ToRequest,
+    {
+        self.encode(&value.to_request(client), buffer);
+    }
+
+    /// Encode a CQC request packet into buffer of bytes.
+    ///
+    /// Returns the number of bytes written or an error if the provided buffer
+    /// is not large enough.  Nothing is written in that case.
+    pub fn try_encode<T>(
+        &self,
+        request: &T,
+        buffer: &mut [u8],
+    ) -> Result<usize, EncodeError>
+    where
+        T: Serialize,
+    {
+        let needed = self.config.serialized_size(request)? as usize;
+        if buffer.len() < needed {
+            return Err(EncodeError::BufferTooSmall {
+                needed,
+                got: buffer.len(),
+            });
+        }
+
+        self.comm_hdr_layout
+            .apply(|| self.config.serialize_into(&mut buffer[..], request))?;
+        Ok(needed)
+    }
+
+    /// Encode a CQC request packet into a newly allocated vector of bytes.
+    pub fn into_vec<T>(&self, request: &T) -> Vec<u8>
+    where
+        T: Serialize,
+    {
+        self.comm_hdr_layout
+            .apply(|| self.config.serialize(&request))
+            .unwrap()
+    }
+
+    /// Encode a CQC request packet onto the end of an existing vector of
+    /// bytes.
+    ///
+    /// Returns the number of bytes appended.  This is useful for building a
+    /// buffer of several packets without allocating a new vector for each.
+    pub fn append_to_vec<T>(&self, request: &T, out: &mut Vec<u8>) -> usize
+    where
+        T: Serialize,
+    {
+        let start = out.len();
+        self.comm_hdr_layout
+            .apply(|| self.config.serialize_into(&mut *out, &request))
+            .unwrap();
+        out.len() - start
+    }
+
+    /// Encode a CQC request packet directly into a writer such as a
+    /// `TcpStream`.
+    pub fn encode_into<W, T>(&self, request: &T, writer: W) -> io::Result<()>
+    where
+        W: io::Write,
+        T: Serialize,
+    {
+        self.comm_hdr_layout
+            .apply(|| self.config.serialize_into(writer, request))
+            .map_err(|err| match *err {
+                bincode::ErrorKind::Io(err) => err,
+                err => io::Error::other(err),
+            })
+    }
+
+    /// Encode each request into its own newly allocated vector of bytes.
+    ///
+    /// The requests are encoded lazily so that a large program split into
+    /// several requests can be sent one packet at a time.
+    pub fn chunked_encode<'a>(
+        &'a self,
+        requests: &'a [Request],
+    ) -> impl Iterator<Item = Vec<u8>> + 'a {
+        requests.iter().map(move |request| self.into_vec(request))
+    }
+}
+
+/// # CQC packet
+///
+/// A packet travelling in either direction, as decoded by
+/// `Decoder::decode_any`.
+#[cfg(feature = "std")]
+#[derive(Debug, PartialEq)]
+pub enum CqcPacket {
+    Request(Request),
+    Response(Response),
+}
+
+/// # Packet decoder
+///
+/// A basic packet decoder.
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+pub struct Decoder {
+    config: bincode::Config,
+    ent_ids: Option<Mutex<HashMap<(SocketAddrV4, SocketAddrV4), u32>>>,
+    lenient: bool,
+}
+
+#[cfg(feature = "std")]
+impl Default for Decoder {
+    fn default() -> Decoder {
+        Decoder::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Decoder {
+    /// Create a big endian `Decoder`.
+    #[allow(deprecated)]
+    pub fn new() -> Decoder {
+        let mut config = bincode::config();
+        config.big_endian();
+
+        Decoder {
+            config,
+            ent_ids: None,
+            lenient: false,
+        }
+    }
+
+    /// Create a little endian `Decoder`.
+    ///
+    /// The CQC wire format is big endian, but some local SimulaQron setups
+    /// use the host byte order instead.
+    #[allow(deprecated)]
+    pub fn little_endian() -> Decoder {
+        let mut config = bincode::config();
+        config.little_endian();
+
+        Decoder {
+            config,
+            ent_ids: None,
+            lenient: false,
+        }
+    }
+
+    /// Create a little endian `Decoder` matching the default of the decoder
+    /// in earlier versions of this crate.
+    ///
+    /// Earlier versions decoded little endian by default, whereas `new` now
+    /// decodes big endian as CQC requires.  Use this constructor when talking
+    /// to peers that still expect the old default.
+    pub fn legacy_little_endian() -> Decoder {
+        Decoder::little_endian()
+    }
+
+    /// Create a big endian `Decoder` which checks that the entanglement IDs
+    /// it decodes never decrease for any pair of nodes.  Nodes are told apart
+    /// by their full socket address since SimulaQron usually runs every node
+    /// on the same host.
+    ///
+    /// The check is made for every response decoded from a whole packet,
+    /// including the responses grouped by a factory.  `decode_rsp_info` only
+    /// decodes a body and does not check it.
+    ///
+    /// This is intended for testing the ID generation of a CQC backend.
+    pub fn with_ent_id_tracking() -> Decoder {
+        Decoder {
+            ent_ids: Some(Mutex::new(HashMap::new())),
+            ..Decoder::new()
+        }
+    }
+
+    /// Create a big endian `Decoder` which accepts message types unknown to
+    /// this version of the crate.
+    ///
+    /// Such a packet is decoded as a response with message type
+    /// `MsgType::Other` and its body as `RspInfo::Raw`.  This allows a
+    /// client to keep talking to a newer backend which sends notifications it
+    /// does not understand.  `decode_cqc_hdr` also returns the unknown
+    /// message type as `MsgType::Other`.  `decode_request`, `parse_header`
+    /// and `validate_bytes` still reject unknown message types.
+    pub fn lenient() -> Decoder {
+        Decoder {
+            lenient: true,
+            ..Decoder::new()
+        }
+    }
+
+    /// Decode supplied data.
+    ///
+    /// Returns a Result which contains either the Response or an error.
+    pub fn decode<T>(&self, buffer: &[u8]) -> Result<T, DecodeError>
+    where
+        T: DeserializeOwned,
+    {
+        let cqc_hdr = self.read_hdr(buffer)?;
+        check_framing(&self.config, &cqc_hdr, buffer)?;
+        let response = MsgType::accept_other(self.lenient, || {
+            self.config.deserialize_from(buffer)
+        })?;
+        if self.ent_ids.is_some() {
+            self.track_packet(&cqc_hdr, buffer)?;
+        }
+        Ok(response)
+    }
+
+    /// Decode supplied data which must hold exactly one packet.
+    ///
+    /// Unlike `decode`, returns an error if the buffer continues past the
+    /// length announced in the CQC Header.
+    pub fn decode_exact<T>(&self, buffer: &[u8]) -> Result<T, DecodeError>
+    where
+        T: DeserializeOwned,
+    {
+        let cqc_hdr = self.read_hdr(buffer)?;
+        let packet_len = packet_len(&cqc_hdr);
+        if buffer.len() > packet_len {
+            return Err(DecodeError::TrailingBytes {
+                packet_len,
+                got: buffer.len(),
+            });
+        }
+
+        self.decode(buffer)
+    }
+
+    /// Decode the first packet in the supplied data.
+    ///
+    /// Returns the packet together with the number of bytes it occupies,
+    /// i.e. the offset of the next packet in the buffer.
+    pub fn decode_with_len<T>(
+        &self,
+        buffer: &[u8],
+    ) -> Result<(T, usize), DecodeError>
+    where
+        T: DeserializeOwned,
+    {
+        let cqc_hdr = self.read_hdr(buffer)?;
+        let packet = self.decode(buffer)?;
+        Ok((packet, packet_len(&cqc_hdr)))
+    }
+
+    /// Read a single packet from a reader such as a `TcpStream` and decode
+    /// it.
+    ///
+    /// The CQC Header is read first to learn the length of the body.  Then
+    /// exactly that many bytes are read so that no bytes of the next packet
+    /// are consumed.
+    pub fn decode_from<R, T>(&self, mut reader: R) -> Result<T, DecodeError>
+    where
+        R: io::Read,
+        T: DeserializeOwned,
+    {
+        use std::io::Read;
+
+        let mut buffer = vec![0; CqcHdr::hdr_len() as usize];
+        reader
+            .read_exact(&mut buffer[..])
+            .map_err(bincode::Error::from)?;
+
+        let cqc_hdr = self.read_hdr(&buffer[..])?;
+
+        // Read through `take` so that a corrupt length does not allocate the
+        // whole body up front.
+        let length = u64::from(cqc_hdr.length);
+        reader
+            .take(length)
+            .read_to_end(&mut buffer)
+            .map_err(bincode::Error::from)?;
+        if buffer.len() < packet_len(&cqc_hdr) {
+            let err = io::Error::from(io::ErrorKind::UnexpectedEof);
+            return Err(bincode::Error::from(err).into());
+        }
+
+        self.decode(&buffer[..])
+    }
+
+    /// Decode the first packet in the supplied data if it has fully arrived.
+    ///
+    /// Returns `Ok(None)` if the buffer does not yet hold the whole packet as
+    /// announced by the length in its CQC Header.  Otherwise returns the
+    /// packet together with the number of bytes it occupies.  This allows a
+    /// reader to accumulate bytes from a stream until a packet is complete.
+    pub fn decode_partial<T>(
+        &self,
+        buffer: &[u8],
+    ) -> Result<Option<(T, usize)>, DecodeError>
+    where
+        T: DeserializeOwned,
+    {
+        if buffer.len() < CqcHdr::hdr_len() as usize {
+            return Ok(None);
+        }
+
+        let cqc_hdr = self.read_hdr(buffer)?;
+        let consumed = packet_len(&cqc_hdr);
+        if buffer.len() < consumed {
+            return Ok(None);
+        }
+
+        let packet = self.decode(&buffer[..consumed])?;
+        Ok(Some((packet, consumed)))
+    }
+
+    /// Check that the supplied buffer holds a valid request without keeping
+    /// the decoded value.
+    ///
+    /// This is a cheap pre-flight check for hand-built packets.
+    pub fn validate_bytes(&self, buffer: &[u8]) -> Result<(), DecodeError> {
+        let cqc_hdr = read_cqc_hdr(&self.config, buffer)?;
+        check_framing(&self.config, &cqc_hdr, buffer)?;
+        let _: Request = self.config.deserialize_from(buffer)?;
+        Ok(())
+    }
+
+    /// Decode only the body of a response whose CQC Header has already been
+    /// decoded.  The buffer must start immediately after the CQC Header.
+    pub fn decode_rsp_info(
+        &self,
+        buffer: &[u8],
+        cqc_hdr: &CqcHdr,
+    ) -> Result<RspInfo, DecodeError> {
+        // A body that is kept as raw bytes is copied in one go.
+        if cqc_hdr.length > 0 && has_raw_body(cqc_hdr.msg_type) {
+            let needed = cqc_hdr.length as usize;
+            if buffer.len() < needed {
+                return Err(DecodeError::Truncated {
+                    needed,
+                    got: buffer.len(),
+                });
+            }
+            return Ok(RspInfo::Raw(buffer[..needed].to_vec()));
+        }
+
+        let rsp_info =
+            self.config.deserialize_seed(RspInfoSeed(cqc_hdr), buffer)?;
+        Ok(rsp_info)
+    }
+
+    /// Decode a request.
+    ///
+    /// Returns an error if the message type is only ever sent by the backend,
+    /// such as the InfTime reply to a GetTime request.
+    pub fn decode_request(
+        &self,
+        buffer: &[u8],
+    ) -> Result<Request, DecodeError> {
+        let cqc_hdr = read_cqc_hdr(&self.config, buffer)?;
+        if !is_request_type(cqc_hdr.msg_type) {
+            return Err(DecodeError::NotRequest(cqc_hdr.msg_type));
+        }
+
+        self.decode(buffer)
+    }
+
+    /// Decode a response.
+    ///
+    /// Returns an error if the message type is only ever sent by the client,
+    /// such as a GetTime request.
+    ///
+    /// If the decoder was created with `with_ent_id_tracking` the entanglement
+    /// ID of an EPR response is checked against the last ID seen for the same
+    /// node pair.
+    pub fn decode_response(
+        &self,
+        buffer: &[u8],
+    ) -> Result<Response, DecodeError> {
+        let cqc_hdr = self.read_hdr(buffer)?;
+        if cqc_hdr.msg_type.is_other() {
+            return self.decode_other(buffer, cqc_hdr);
+        }
+
+        if !is_response_type(cqc_hdr.msg_type) {
+            return Err(DecodeError::NotResponse(cqc_hdr.msg_type));
+        }
+
+        check_framing(&self.config, &cqc_hdr, buffer)?;
+        let body = &buffer[CqcHdr::hdr_len() as usize..packet_len(&cqc_hdr)];
+        let notify = self.decode_rsp_info(body, &cqc_hdr)?;
+        self.track_ent_ids(&notify)?;
+        Ok(Response { cqc_hdr, notify })
+    }
+
+    /// Decode a packet without knowing in advance whether it is a request
+    /// or a response.
+    ///
+    /// The packet is classified by the message type in its CQC Header.
+    /// Hello and Factory are sent in both directions and are decoded as
+    /// requests first.  A Factory packet which is not a valid request is
+    /// decoded as the grouped responses of a factory instead.
+    pub fn decode_any(&self, buffer: &[u8]) -> Result<CqcPacket, DecodeError> {
+        let cqc_hdr = self.read_hdr(buffer)?;
+        if !is_request_type(cqc_hdr.msg_type) {
+            return Ok(CqcPacket::Response(self.decode_response(buffer)?));
+        }
+
+        let request = check_framing(&self.config, &cqc_hdr, buffer)
+            .and_then(|()| Ok(self.config.deserialize_from(buffer)?));
+        match request {
+            Ok(request) => Ok(CqcPacket::Request(request)),
+            Err(err) => {
+                if cqc_hdr.msg_type != MsgType::Tp(Tp::Factory) {
+                    return Err(err);
+                }
+
+                match self.decode_response(buffer) {
+                    Ok(response) => Ok(CqcPacket::Response(response)),
+                    Err(_) => Err(err),
+                }
+            }
+        }
+    }
+
+    /// Decode every packet in a buffer holding both requests and responses,
+    /// such as a stream captured by a proxy, in the order they appear.
+    ///
+    /// Each packet is decoded with `decode_any`.  Iteration stops after the
+    /// first error since the start of the next packet is then unknown.
+    pub fn decode_any_stream<'a>(
+        &'a self,
+        buffer: &'a [u8],
+    ) -> impl Iterator<Item = Result<CqcPacket, DecodeError>> + 'a {
+        let mut offset = 0;
+        iter::from_fn(move || {
+            if offset >= buffer.len() {
+                return None;
+            }
+
+            let rest = &buffer[offset..];
+            let result = self.read_hdr(rest).and_then(|cqc_hdr| {
+                let len = packet_len(&cqc_hdr);
+                let len = cmp::min(len, rest.len());
+                offset += len;
+                self.decode_any(&rest[..len])
+            });
+
+            if result.is_err() {
+                offset = buffer.len();
+            }
+            Some(result)
+        })
+    }
+
+    /// Decode only the CQC Header in the first eight bytes of the supplied
+    /// packet.  The message type and length can be used for framing and
+    /// routing before the body is decoded.
+    pub fn decode_cqc_hdr(
+        &self,
+        buffer: &[u8],
+    ) -> Result<CqcHdr, DecodeError> {
+        self.read_hdr(buffer)
+    }
+
+    /// Decode only the CQC header of the supplied packet.
+    ///
+    /// Returns the CQC header together with a `BodyCursor` which can be used
+    /// to decode the full packet later.  The body is not parsed until
+    /// `BodyCursor::parse` is called which allows a router to inspect the
+    /// header and skip packets it is not interested in.
+    pub fn parse_header<'buf>(
+        &'buf self,
+        buffer: &'buf [u8],
+    ) -> Result<(CqcHdr, BodyCursor<'buf>), DecodeError> {
+        let cqc_hdr = read_cqc_hdr(&self.config, buffer)?;
+        let cursor = BodyCursor {
+            decoder: self,
+            buffer,
+        };
+        Ok((cqc_hdr, cursor))
+    }
+
+    /// Decode the CQC Header at the start of a packet.  A lenient decoder
+    /// returns an unknown message type as `MsgType::Other`.
+    fn read_hdr(&self, buffer: &[u8]) -> Result<CqcHdr, DecodeError> {
+        match read_cqc_hdr(&self.config, buffer) {
+            Err(DecodeError::InvalidMessageType(msg_type)) if self.lenient => {
+                let (app_id, length) = self
+                    .config
+                    .deserialize(&buffer[2..CqcHdr::hdr_len() as usize])?;
+                Ok(CqcHdr {
+                    version: Version::get(buffer[0]).unwrap(),
+                    msg_type: MsgType::Other(msg_type),
+                    app_id,
+                    length,
+                })
+            }
+            result => result,
+        }
+    }
+
+    /// Check the entanglement IDs of a packet decoded by `decode`, which does
+    /// not know whether it decoded a response.  Only EprOk and Factory
+    /// responses carry entanglement information.  A Factory packet whose body
+    /// is not a valid response body is a request and carries none.
+    fn track_packet(
+        &self,
+        cqc_hdr: &CqcHdr,
+        buffer: &[u8],
+    ) -> Result<(), DecodeError> {
+        let is_factory = match cqc_hdr.msg_type {
+            MsgType::Tp(Tp::EprOk) => false,
+            MsgType::Tp(Tp::Factory) => true,
+            _ => return Ok(()),
+        };
+
+        let body = &buffer[CqcHdr::hdr_len() as usize..packet_len(cqc_hdr)];
+        match self.decode_rsp_info(body, cqc_hdr) {
+            Ok(notify) => self.track_ent_ids(&notify),
+            Err(_) if is_factory => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Check that the entanglement IDs in a response body do not decrease
+    /// for any pair of nodes if the decoder tracks them.
+    fn track_ent_ids(&self, notify: &RspInfo) -> Result<(), DecodeError> {
+        let ent_ids = match self.ent_ids {
+            Some(ref ent_ids) => ent_ids,
+            None => return Ok(()),
+        };
+
+        match *notify {
+            RspInfo::Epr(ref epr_info) => {
+                let ent_info = &epr_info.ent_info_hdr;
+                let id = ent_info.id_ab;
+
+                // Nothing panics while the lock is held so it is never
+                // poisoned.
+                let mut ent_ids = ent_ids.lock().unwrap();
+                let key = (ent_info.addr_a(), ent_info.addr_b());
+                let last = ent_ids.entry(key).or_insert(id);
+                if id < *last {
+                    return Err(DecodeError::NonMonotonicEntId {
+                        node_a: ent_info.node_a,
+                        node_b: ent_info.node_b,
+                        last: *last,
+                        id,
+                    });
+                }
+                *last = id;
+            }
+            RspInfo::Factory(ref rsps) => {
+                for rsp in rsps {
+                    self.track_ent_ids(&rsp.notify)?;
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Decode a response of a message type unknown to this version of the
+    /// crate.  Its body, if any, is kept as raw bytes.
+    fn decode_other(
+        &self,
+        buffer: &[u8],
+        cqc_hdr: CqcHdr,
+    ) -> Result<Response, DecodeError> {
+        let needed = packet_len(&cqc_hdr);
+        if buffer.len() < needed {
+            return Err(DecodeError::Truncated {
+                needed,
+                got: buffer.len(),
+            });
+        }
+
+        let body = &buffer[CqcHdr::hdr_len() as usize..needed];
+        let notify = self.decode_rsp_info(body, &cqc_hdr)?;
+        Ok(Response { cqc_hdr, notify })
+    }
+}
+
+/// # Body cursor
+///
+/// A deferred parser for a packet whose CQC header has already been decoded
+/// by `Decoder::parse_header`.
+#[cfg(feature = "std")]
+pub struct BodyCursor<'buf> {
+    decoder: &'buf Decoder,
+    buffer: &'buf [u8],
+}
+
+#[cfg(feature = "std")]
+impl<'buf> BodyCursor<'buf> {
+    /// Decode the full packet, header and body.
+    pub fn parse<T>(&self) -> Result<T, DecodeError>
+    where
+        T: DeserializeOwned,
+    {
+        self.decoder.decode(self.buffer)
+    }
+}
+
+/// # Packet framer
+///
+/// Splits a byte stream such as a `TcpStream` into whole CQC packets without
+/// decoding them.  The bytes of each packet can then be passed to a
+/// `Decoder`.
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+pub struct Framer {
+    config: bincode::Config,
+}
+
+#[cfg(feature = "std")]
+impl Default for Framer {
+    fn default() -> Framer {
+        Framer::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Framer {
+    /// Create a big endian `Framer`.
+    #[allow(deprecated)]
+    pub fn new() -> Framer {
+        let mut config = bincode::config();
+        config.big_endian();
+        Framer { config }
+    }
+
+    /// Create a little endian `Framer`.
+    #[allow(deprecated)]
+    pub fn little_endian() -> Framer {
+        let mut config = bincode::config();
+        config.little_endian();
+        Framer { config }
+    }
+
+    /// Read a single packet from a reader.
+    ///
+    /// The CQC Header is read first to learn the length of the body.  Then
+    /// exactly that many bytes are read so that no bytes of the next packet
+    /// are consumed.  Returns the whole packet, CQC Header included.  A CQC
+    /// Header that cannot be decoded is reported as `InvalidData`.
+    pub fn read_packet<R>(&self, reader: &mut R) -> io::Result<Vec<u8>>
+    where
+        R: io::Read,
+    {
+        use std::io::Read;
+
+        let mut buffer = vec![0; CqcHdr::hdr_len() as usize];
+        reader.read_exact(&mut buffer[..])?;
+
+        let cqc_hdr: CqcHdr = self
+            .config
+            .deserialize(&buffer[..])
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        // Read through `take` so that a corrupt length does not allocate the
+        // whole body up front.
+        let length = u64::from(cqc_hdr.length);
+        reader.by_ref().take(length).read_to_end(&mut buffer)?;
+        if buffer.len() < packet_len(&cqc_hdr) {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        Ok(buffer)
+    }
+}
+
+/// Check whether messages of this type must carry at least one command.
+fn has_cmd_body(msg_type: MsgType) -> bool {
+    matches!(
+        msg_type,
+        MsgType::Tp(Tp::Command)
+            | MsgType::Tp(Tp::Factory)
+            | MsgType::Tp(Tp::GetTime)
+    )
+}
+
+/// Check whether messages of this type are sent by the client.
+#[cfg(feature = "std")]
+fn is_request_type(msg_type: MsgType) -> bool {
+    matches!(
+        msg_type,
+        MsgType::Tp(Tp::Hello)
+            | MsgType::Tp(Tp::Command)
+            | MsgType::Tp(Tp::Factory)
+            | MsgType::Tp(Tp::GetTime)
+            | MsgType::Tp(Tp::Mix)
+            | MsgType::Tp(Tp::If)
+    )
+}
+
+/// Check whether messages of this type are sent by the backend.
+#[cfg(feature = "std")]
+fn is_response_type(msg_type: MsgType) -> bool {
+    !matches!(
+        msg_type,
+        MsgType::Tp(Tp::Command)
+            | MsgType::Tp(Tp::GetTime)
+            | MsgType::Tp(Tp::Mix)
+            | MsgType::Tp(Tp::If)
+    )
+}
+
+/// Decode the CQC Header at the start of a packet.  The version and message
+/// type are checked first so that they can be reported as specific
+/// `DecodeError` variants.
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+fn read_cqc_hdr(
+    config: &bincode::Config,
+    buffer: &[u8],
+) -> Result<CqcHdr, DecodeError> {
+    let needed = CqcHdr::hdr_len() as usize;
+    if buffer.len() < needed {
+        return Err(DecodeError::Truncated {
+            needed,
+            got: buffer.len(),
+        });
+    }
+
+    if Version::get(buffer[0]).is_none() {
+        return Err(DecodeError::UnsupportedVersion(buffer[0]));
+    }
+
+    if MsgType::get(buffer[1]).is_none() {
+        return Err(DecodeError::InvalidMessageType(buffer[1]));
+    }
+
+    Ok(config.deserialize_from(buffer)?)
+}
+
+/// The length of the packet announced by a CQC Header.  Computed in `usize`
+/// so that a corrupt length field cannot overflow.
+#[cfg(feature = "std")]
+fn packet_len(cqc_hdr: &CqcHdr) -> usize {
+    CqcHdr::hdr_len() as usize + cqc_hdr.length as usize
+}
+
+/// Reject a packet whose framing, as announced by its already decoded CQC
+/// Header, is malformed.  These checks run before the packet is deserialised
+/// so that the errors can be reported as specific `DecodeError` variants.
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+fn check_framing(
+    config: &bincode::Config,
+    cqc_hdr: &CqcHdr,
+    buffer: &[u8],
+) -> Result<(), DecodeError> {
+    let needed = packet_len(cqc_hdr);
+    if buffer.len() < needed {
+        return Err(DecodeError::Truncated {
+            needed,
+            got: buffer.len(),
+        });
+    }
+
+    if cqc_hdr.length == 0 && has_cmd_body(cqc_hdr.msg_type) {
+        return Err(DecodeError::EmptyCommandBody(cqc_hdr.msg_type));
+    }
+
+    if cqc_hdr.msg_type == MsgType::Tp(Tp::Factory)
+        && cqc_hdr.length == FactoryHdr::hdr_len()
+    {
+        return Err(DecodeError::EmptyFactoryBody);
+    }
+
+    if cqc_hdr.msg_type == MsgType::Tp(Tp::Mix) {
+        let start = CqcHdr::hdr_len() as usize;
+        let end = (start + cqc_hdr.length as usize).min(buffer.len());
+        check_type_hdrs(config, &buffer[start..end])?;
+    }
+
+    Ok(())
+}
+
+/// Walk the Type Headers of a Mix program and check that none of them
+/// announces more bytes than remain in the program.  Stops at the first Type
+/// Header that cannot be read and leaves that error to the deserialiser.
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+fn check_type_hdrs(
+    config: &bincode::Config,
+    body: &[u8],
+) -> Result<(), DecodeError> {
+    let mut pos = 0;
+
+    while pos + TypeHdr::hdr_len() as usize <= body.len() {
+        let type_hdr: TypeHdr = match config.deserialize(&body[pos..]) {
+            Ok(type_hdr) => type_hdr,
+            Err(_) => break,
+        };
+
+        pos += TypeHdr::hdr_len() as usize;
+        let remaining = (body.len() - pos) as u32;
+        if type_hdr.length > remaining {
+            return Err(DecodeError::TypeHeaderOverrun {
+                declared: type_hdr.length,
+                remaining,
+            });
+        }
+
+        pos += type_hdr.length as usize;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,128 @@
+//! # Gate Matrices
+//!
+//! Unitary matrices of the single-qubit CQC instructions in the computational
+//! basis.  These are intended for checking and explaining the gate set, not
+//! for simulation.
+
+use hdr::Cmd;
+use std::ops::{Add, Mul};
+
+/// # Complex number
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    /// Construct a complex number from its real and imaginary parts.
+    #[inline]
+    pub const fn new(re: f64, im: f64) -> Self {
+        Complex { re, im }
+    }
+
+    /// Check whether two complex numbers are equal to within `eps`.
+    #[inline]
+    pub fn approx_eq(self, other: Complex, eps: f64) -> bool {
+        (self.re - other.re).abs() < eps && (self.im - other.im).abs() < eps
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+/// A single-qubit state as the amplitudes of |0> and |1>.
+pub type State = [Complex; 2];
+
+/// A single-qubit gate, indexed by row then column.
+pub type Matrix = [[Complex; 2]; 2];
+
+const ZERO: Complex = Complex::new(0.0, 0.0);
+const ONE: Complex = Complex::new(1.0, 0.0);
+const I: Complex = Complex::new(0.0, 1.0);
+
+/// The matrix of a single-qubit instruction that takes no extra header.
+/// Returns `None` for all other instructions.
+///
+/// - K is (Y + Z)/sqrt(2).  It takes |0> to the +1 eigenstate of Y and |1>
+///   to the -1 eigenstate of Y (up to a global phase), and is its own
+///   inverse.
+pub fn gate(instr: Cmd) -> Option<Matrix> {
+    let r = ::std::f64::consts::FRAC_1_SQRT_2;
+    let matrix = match instr {
+        Cmd::I => [[ONE, ZERO], [ZERO, ONE]],
+        Cmd::X => [[ZERO, ONE], [ONE, ZERO]],
+        Cmd::Y => [[ZERO, Complex::new(0.0, -1.0)], [I, ZERO]],
+        Cmd::Z => [[ONE, ZERO], [ZERO, Complex::new(-1.0, 0.0)]],
+        Cmd::T => [[ONE, ZERO], [ZERO, Complex::new(r, r)]],
+        Cmd::H => [
+            [Complex::new(r, 0.0), Complex::new(r, 0.0)],
+            [Complex::new(r, 0.0), Complex::new(-r, 0.0)],
+        ],
+        Cmd::K => [
+            [Complex::new(r, 0.0), Complex::new(0.0, -r)],
+            [Complex::new(0.0, r), Complex::new(-r, 0.0)],
+        ],
+        _ => return None,
+    };
+
+    Some(matrix)
+}
+
+/// Apply a gate to a single-qubit state.
+pub fn apply(matrix: &Matrix, state: &State) -> State {
+    [
+        matrix[0][0] * state[0] + matrix[0][1] * state[1],
+        matrix[1][0] * state[0] + matrix[1][1] * state[1],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f64 = 1e-12;
+
+    fn approx_eq(lhs: &State, rhs: &State) -> bool {
+        lhs[0].approx_eq(rhs[0], EPS) && lhs[1].approx_eq(rhs[1], EPS)
+    }
+
+    fn scale(factor: Complex, state: &State) -> State {
+        [factor * state[0], factor * state[1]]
+    }
+
+    #[test]
+    fn k_basis_change() {
+        let k = gate(Cmd::K).unwrap();
+        let y = gate(Cmd::Y).unwrap();
+        let minus_one = Complex::new(-1.0, 0.0);
+
+        // K|0> is the +1 eigenstate of Y.
+        let plus_i = apply(&k, &[ONE, ZERO]);
+        assert!(approx_eq(&apply(&y, &plus_i), &plus_i));
+
+        // K|1> is the -1 eigenstate of Y.
+        let minus_i = apply(&k, &[ZERO, ONE]);
+        assert!(approx_eq(&apply(&y, &minus_i), &scale(minus_one, &minus_i)));
+
+        // K is its own inverse.
+        let state = [Complex::new(0.6, 0.0), Complex::new(0.0, 0.8)];
+        assert!(approx_eq(&apply(&k, &apply(&k, &state)), &state));
+    }
+}
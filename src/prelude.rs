@@ -0,0 +1,26 @@
+//! # Prelude
+//!
+//! The types used by most programs that build, encode, and decode CQC
+//! packets.  `hdr::Err` is left out as it would shadow `Result::Err`.
+//!
+//! ```
+//! use cqc::prelude::*;
+//!
+//! let client = Client::new(10);
+//! let request: Request = client.cmd_new(0, CmdOpt::notify());
+//! assert_eq!(request.cqc_hdr.msg_type, MsgType::Tp(Tp::Command));
+//!
+//! // The encoder and decoder need the `std` feature.
+//! #[cfg(feature = "std")]
+//! {
+//!     let buffer = Encoder::new().into_vec(&request);
+//!     let decoded: Request = Decoder::new().decode(&buffer).unwrap();
+//!     assert_eq!(decoded, request);
+//! }
+//! ```
+
+pub use builder::{Client, RemoteId, Server};
+pub use hdr::{Cmd, CmdOpt, CqcHdr, MeasOut, MsgType, Tp};
+#[cfg(feature = "std")]
+pub use {Decoder, Encoder};
+pub use {Request, Response, RspInfo};
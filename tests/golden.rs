@@ -0,0 +1,133 @@
+extern crate cqc;
+
+// Golden wire-format fixtures.  The byte vectors follow the struct packing
+// used by SimulaQron's CQC implementation (network byte order, no padding) so
+// that any change in field order or endianness in this crate shows up as a
+// mismatch here.
+#[cfg(test)]
+mod golden {
+    use cqc::builder::{Client, RemoteId, Server};
+    use cqc::hdr::*;
+    use cqc::{Decoder, Encoder, Request, Response, RspInfo};
+
+    // Application 10 on localhost nodes 8803 and 8804.
+    const APP_ID: u16 = 10;
+    const LOCALHOST: u32 = 0x7F_00_00_01;
+    const LOCAL_PORT: u16 = 8803;
+    const REMOTE_PORT: u16 = 8804;
+
+    // New qubit request with notify and block set.
+    const CMD_NEW: [u8; 12] = [
+        0x02, 0x01, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x04, // CQC header
+        0x00, 0x00, 0x01, 0x05, // CMD header
+    ];
+
+    // Send qubit 1 to the remote node with notify set.
+    const CMD_SEND: [u8; 20] = [
+        0x02, 0x01, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x0C, // CQC header
+        0x00, 0x01, 0x05, 0x01, // CMD header
+        0x00, 0x0A, 0x22, 0x64, 0x7F, 0x00, 0x00, 0x01, // COMM header
+    ];
+
+    // Qubit 3 created as half of EPR pair 7.
+    const EPR_OK: [u8; 50] = [
+        0x02, 0x06, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x2A, // CQC header
+        0x00, 0x03, // Qubit header
+        0x7F, 0x00, 0x00, 0x01, 0x22, 0x63, 0x00, 0x0A, // node A
+        0x7F, 0x00, 0x00, 0x01, 0x22, 0x64, 0x00, 0x0A, // node B
+        0x00, 0x00, 0x00, 0x07, // id_AB
+        0x00, 0x00, 0x00, 0x00, 0x5C, 0x8F, 0x3A, 0x10, // timestamp
+        0x00, 0x00, 0x00, 0x00, 0x5C, 0x8F, 0x3A, 0x20, // ToG
+        0x00, 0x63, // goodness
+        0x01, 0x00, // DF and align
+    ];
+
+    // Measurement outcome of one.
+    const MEAS_OUT: [u8; 9] = [
+        0x02, 0x07, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x01, // CQC header
+        0x01, // Measurement outcome header
+    ];
+
+    #[test]
+    fn cmd_new() {
+        let client = Client::new(APP_ID);
+        let request =
+            client.cmd_new(0, *CmdOpt::empty().set_notify().set_block());
+
+        let decoder = Decoder::new();
+        let decoded: Request = decoder.decode(&CMD_NEW[..]).unwrap();
+        assert_eq!(decoded, request);
+
+        let encoder = Encoder::new();
+        assert_eq!(encoder.into_vec(&decoded), CMD_NEW.to_vec());
+    }
+
+    #[test]
+    fn cmd_send() {
+        let client = Client::new(APP_ID);
+        let request = client.cmd_send(
+            1,
+            *CmdOpt::empty().set_notify(),
+            RemoteId {
+                remote_app_id: APP_ID,
+                remote_node: LOCALHOST,
+                remote_port: REMOTE_PORT,
+            },
+        );
+
+        let decoder = Decoder::new();
+        let decoded: Request = decoder.decode(&CMD_SEND[..]).unwrap();
+        assert_eq!(decoded, request);
+
+        let encoder = Encoder::new();
+        assert_eq!(encoder.into_vec(&decoded), CMD_SEND.to_vec());
+    }
+
+    #[test]
+    fn epr_ok() {
+        let server = Server::new(APP_ID);
+        let response = server.epr_ok(
+            3,
+            EntInfoHdr {
+                node_a: LOCALHOST,
+                port_a: LOCAL_PORT,
+                app_id_a: APP_ID,
+                node_b: LOCALHOST,
+                port_b: REMOTE_PORT,
+                app_id_b: APP_ID,
+                id_ab: 7,
+                timestamp: 0x5C_8F_3A_10,
+                tog: 0x5C_8F_3A_20,
+                goodness: 99,
+                df: 1,
+                align: 0,
+            },
+        );
+
+        let decoder = Decoder::new();
+        let decoded: Response = decoder.decode(&EPR_OK[..]).unwrap();
+        assert_eq!(decoded, response);
+        if let RspInfo::Epr(ref epr_info) = decoded.notify {
+            assert_eq!(
+                epr_info.ent_info_hdr.directionality(),
+                Some(Directionality::NodeA)
+            );
+        }
+
+        let encoder = Encoder::new();
+        assert_eq!(encoder.into_vec(&decoded), EPR_OK.to_vec());
+    }
+
+    #[test]
+    fn meas_out() {
+        let server = Server::new(APP_ID);
+        let response = server.meas_out(MeasOut::One);
+
+        let decoder = Decoder::new();
+        let decoded: Response = decoder.decode(&MEAS_OUT[..]).unwrap();
+        assert_eq!(decoded, response);
+
+        let encoder = Encoder::new();
+        assert_eq!(encoder.into_vec(&decoded), MEAS_OUT.to_vec());
+    }
+}
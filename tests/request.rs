@@ -2,9 +2,17 @@ extern crate cqc;
 
 #[cfg(test)]
 mod request {
-    use cqc::builder::{Client, RemoteId};
+    use cqc::builder::{
+        Axis, Client, Pauli, ProgramBuilder, RemoteId, Server, ToRequest,
+    };
     use cqc::hdr::*;
-    use cqc::{Decoder, Encoder, Request};
+    use cqc::{
+        BuildError, CqcPacket, DecodeError, Decoder, EncodeError, Encoder,
+        FieldDiff, Framer, HeaderKind, Request,
+    };
+    use cqc::{FactoryReq, MixSeg, ReqBody, ReqCmd, ValidationError, XtraHdr};
+    use std::io;
+    use std::thread;
 
     macro_rules! get_byte_16 {
         ($value:expr, $byte:expr) => {
@@ -26,6 +34,8 @@ mod request {
     const REMOTE_NODE: u32 = 0xAE_04_E2_52;
     const REMOTE_PORT: u16 = 0x91_03;
     const STEP: u8 = 192;
+    const LEFT_REF: u32 = 0x01_23_45_67;
+    const RIGHT_REF: u32 = 0x89_AB_CD_EF;
 
     // Encode a request packet that only has a CQC header.
     #[test]
@@ -106,6 +116,94 @@ mod request {
         assert_eq!(decoded, request);
     }
 
+    // Encode a qubit allocation request.  The count is carried in the qubit
+    // ID field.
+    #[test]
+    fn cmd_allocate() {
+        let client = Client::new(APP_ID);
+        let num_qubits: u16 = 5;
+        let request = client.cmd_allocate(num_qubits, CmdOpt::empty());
+
+        // Buffer to write into.
+        let buf_len: usize = request.len() as usize;
+        let mut buffer = vec![0xAA; buf_len];
+
+        // Expected values
+        let msg_type = MsgType::Tp(Tp::Command);
+        let length = CmdHdr::hdr_len();
+        let options = CmdOpt::empty();
+
+        // Big-endian
+        let expected: Vec<u8> = vec![
+            // CQC header
+            Version::V2 as u8,
+            From::from(msg_type),
+            get_byte_16!(APP_ID, 0),
+            get_byte_16!(APP_ID, 1),
+            get_byte_32!(length, 0),
+            get_byte_32!(length, 1),
+            get_byte_32!(length, 2),
+            get_byte_32!(length, 3),
+            // CMD header
+            get_byte_16!(num_qubits, 0),
+            get_byte_16!(num_qubits, 1),
+            22,
+            options.bits(),
+        ];
+
+        let encoder = Encoder::new();
+        encoder.encode(&request, &mut buffer[..]);
+        assert_eq!(buffer, expected);
+
+        let decoder = Decoder::new();
+        let decoded: Request = decoder.decode(&buffer[..]).unwrap();
+        assert_eq!(decoded, request);
+    }
+
+    // Encode a qubit release request.
+    #[test]
+    fn cmd_release() {
+        let client = Client::new(APP_ID);
+        let request =
+            client.cmd_release(QUBIT_ID, *CmdOpt::empty().set_notify());
+
+        // Buffer to write into.
+        let buf_len: usize = request.len() as usize;
+        let mut buffer = vec![0xAA; buf_len];
+
+        // Expected values
+        let msg_type = MsgType::Tp(Tp::Command);
+        let length = CmdHdr::hdr_len();
+        let instr = Cmd::Release;
+        let options = *CmdOpt::empty().set_notify();
+
+        // Big-endian
+        let expected: Vec<u8> = vec![
+            // CQC header
+            Version::V2 as u8,
+            From::from(msg_type),
+            get_byte_16!(APP_ID, 0),
+            get_byte_16!(APP_ID, 1),
+            get_byte_32!(length, 0),
+            get_byte_32!(length, 1),
+            get_byte_32!(length, 2),
+            get_byte_32!(length, 3),
+            // CMD header
+            get_byte_16!(QUBIT_ID, 0),
+            get_byte_16!(QUBIT_ID, 1),
+            instr as u8,
+            options.bits(),
+        ];
+
+        let encoder = Encoder::new();
+        encoder.encode(&request, &mut buffer[..]);
+        assert_eq!(buffer, expected);
+
+        let decoder = Decoder::new();
+        let decoded: Request = decoder.decode(&buffer[..]).unwrap();
+        assert_eq!(decoded, request);
+    }
+
     // Encode a packet with a CMD and ROT headers.
     #[test]
     fn rot_hdr() {
@@ -265,57 +363,140 @@ mod request {
         assert_eq!(decoded, request);
     }
 
-    // Test an encoding when the provided buffer is too small (should panic).
+    // Encode a send command with the Communication Header fields in the
+    // alternative order.
     #[test]
-    #[should_panic(expected = "failed to write whole buffer")]
-    fn cqc_hdr_buf_too_small() {
+    fn comm_hdr_layout() {
         let client = Client::new(APP_ID);
-        let request = client.hello();
+        let request = client.cmd_send(
+            QUBIT_ID,
+            CmdOpt::empty(),
+            RemoteId {
+                remote_app_id: REMOTE_APP_ID,
+                remote_node: REMOTE_NODE,
+                remote_port: REMOTE_PORT,
+            },
+        );
 
-        // Buffer to write into.
-        let mut buffer = vec![0xAA; (request.len() - 1) as usize];
+        let port_node = Encoder::new().into_vec(&request);
+        let node_port = Encoder::new()
+            .comm_hdr_layout(CommHdrLayout::AppNodePort)
+            .into_vec(&request);
+        assert_eq!(port_node.len(), node_port.len());
+        assert_ne!(port_node, node_port);
 
-        let encoder = Encoder::new();
+        // Everything up to and including the remote application ID agrees.
+        let offset = (CqcHdr::hdr_len() + CmdHdr::hdr_len() + 2) as usize;
+        assert_eq!(port_node[..offset], node_port[..offset]);
 
-        // This should panic.
+        assert_eq!(
+            port_node[offset..],
+            [
+                get_byte_16!(REMOTE_PORT, 0),
+                get_byte_16!(REMOTE_PORT, 1),
+                get_byte_32!(REMOTE_NODE, 0),
+                get_byte_32!(REMOTE_NODE, 1),
+                get_byte_32!(REMOTE_NODE, 2),
+                get_byte_32!(REMOTE_NODE, 3),
+            ]
+        );
+        assert_eq!(
+            node_port[offset..],
+            [
+                get_byte_32!(REMOTE_NODE, 0),
+                get_byte_32!(REMOTE_NODE, 1),
+                get_byte_32!(REMOTE_NODE, 2),
+                get_byte_32!(REMOTE_NODE, 3),
+                get_byte_16!(REMOTE_PORT, 0),
+                get_byte_16!(REMOTE_PORT, 1),
+            ]
+        );
+
+        // Every encoding method applies the layout.
+        let encoder =
+            Encoder::new().comm_hdr_layout(CommHdrLayout::AppNodePort);
+        let mut written: Vec<u8> = Vec::new();
+        encoder.encode_into(&request, &mut written).unwrap();
+        assert_eq!(written, node_port);
+        let mut buffer = vec![0; node_port.len()];
         encoder.encode(&request, &mut buffer[..]);
+        assert_eq!(buffer, node_port);
     }
 
-    // Test an encoding when the provided buffer is too small, but sufficient
-    // for the CQC header (should panic).
+    // Encode a Communication Header in the alternative order inside a Mix
+    // program which also measures a qubit.
     #[test]
-    #[should_panic(expected = "failed to write whole buffer")]
-    fn cmd_hdr_buf_too_small() {
+    fn comm_hdr_layout_mix() {
         let client = Client::new(APP_ID);
-        let request = client.cmd_i(QUBIT_ID, CmdOpt::empty());
-
-        // Buffer to write into.
-        let mut buffer = vec![0xAA; (request.len() - 1) as usize];
+        let mut measure = client
+            .cmd_measure(QUBIT_ID, CmdOpt::empty())
+            .body
+            .get_cmd()
+            .remove(0);
+        measure.xtra_hdr = XtraHdr::Assign(AssignHdr { ref_id: LEFT_REF });
+        let send = client
+            .cmd_send(
+                QUBIT_ID,
+                CmdOpt::empty(),
+                RemoteId {
+                    remote_app_id: REMOTE_APP_ID,
+                    remote_node: REMOTE_NODE,
+                    remote_port: REMOTE_PORT,
+                },
+            )
+            .body
+            .get_cmd()
+            .remove(0);
+        let request = client
+            .mix()
+            .push_command(measure)
+            .push_command(send)
+            .build();
 
-        let encoder = Encoder::new();
+        let port_node = Encoder::new().into_vec(&request);
+        let node_port = Encoder::new()
+            .comm_hdr_layout(CommHdrLayout::AppNodePort)
+            .into_vec(&request);
+        assert_eq!(port_node.len(), node_port.len());
 
-        // This should panic.
-        encoder.encode(&request, &mut buffer[..]);
+        // Only the last six bytes, the port and the node of the send
+        // command, differ.
+        let offset = node_port.len() - 6;
+        assert_eq!(port_node[..offset], node_port[..offset]);
+        assert_eq!(
+            node_port[offset..],
+            [
+                get_byte_32!(REMOTE_NODE, 0),
+                get_byte_32!(REMOTE_NODE, 1),
+                get_byte_32!(REMOTE_NODE, 2),
+                get_byte_32!(REMOTE_NODE, 3),
+                get_byte_16!(REMOTE_PORT, 0),
+                get_byte_16!(REMOTE_PORT, 1),
+            ]
+        );
     }
 
-    // Test an encoding when the provided buffer is too large.  Excess should
-    // be untouched.
+    // Encode a measurement which stores its outcome under a reference ID.
     #[test]
-    fn buf_too_large() {
+    fn assign_hdr() {
         let client = Client::new(APP_ID);
-        let request = client.hello();
+        let request =
+            client.cmd_measure_assign(QUBIT_ID, CmdOpt::empty(), LEFT_REF);
 
         // Buffer to write into.
-        let write_len: usize = request.len() as usize;
-        let buf_len: usize = write_len + 4;
-        let mut buffer = vec![0xAA; buf_len as usize];
+        let buf_len: usize = request.len() as usize;
+        let mut buffer = vec![0xAA; buf_len];
 
         // Expected values
-        let msg_type = MsgType::Tp(Tp::Hello);
-        let length = 0;
+        let msg_type = MsgType::Tp(Tp::Mix);
+        let seg_length = CmdHdr::hdr_len() + AssignHdr::hdr_len();
+        let length = TypeHdr::hdr_len() + seg_length;
+        let instr = Cmd::Measure;
+        let options = CmdOpt::empty();
 
         // Big-endian
         let expected: Vec<u8> = vec![
+            // CQC header
             Version::V2 as u8,
             From::from(msg_type),
             get_byte_16!(APP_ID, 0),
@@ -324,11 +505,22 @@ mod request {
             get_byte_32!(length, 1),
             get_byte_32!(length, 2),
             get_byte_32!(length, 3),
-            // The rest should be untouched.
-            0xAA,
-            0xAA,
-            0xAA,
-            0xAA,
+            // Type header
+            Tp::Command as u8,
+            get_byte_32!(seg_length, 0),
+            get_byte_32!(seg_length, 1),
+            get_byte_32!(seg_length, 2),
+            get_byte_32!(seg_length, 3),
+            // CMD header
+            get_byte_16!(QUBIT_ID, 0),
+            get_byte_16!(QUBIT_ID, 1),
+            instr as u8,
+            options.bits(),
+            // Assign header
+            get_byte_32!(LEFT_REF, 0),
+            get_byte_32!(LEFT_REF, 1),
+            get_byte_32!(LEFT_REF, 2),
+            get_byte_32!(LEFT_REF, 3),
         ];
 
         let encoder = Encoder::new();
@@ -340,17 +532,34 @@ mod request {
         assert_eq!(decoded, request);
     }
 
-    // Decode a request that only has a non-zero length indicating follow-up
-    // headers, but it is too short to hold the expected header. This should
-    // return an Error and thus panic on unwrap.
+    // Encode and decode a factory repeating a Y rotation.
     #[test]
-    #[should_panic(expected = "invalid length 3, expected CmdHdr")]
-    fn invalid_len() {
-        let msg_type = MsgType::Tp(Tp::Command);
-        let length = CmdHdr::hdr_len() - 1;
-        let instr = Cmd::New;
-        let options = *CmdOpt::empty().set_notify().set_block();
+    fn factory_hdr() {
+        let client = Client::new(APP_ID);
+        let req_cmd = ReqCmd {
+            cmd_hdr: CmdHdr {
+                qubit_id: QUBIT_ID,
+                instr: Cmd::RotY,
+                options: CmdOpt::empty(),
+            },
+            xtra_hdr: XtraHdr::Rot(RotHdr { step: STEP }),
+        };
+        let num_iter = 10;
+        let factory_opt = *FactoryOpt::empty().set_notify().set_block();
+        let request = client.factory(num_iter, factory_opt, req_cmd);
+
+        // Buffer to write into.
+        let buf_len: usize = request.len() as usize;
+        let mut buffer = vec![0xAA; buf_len];
+
+        // Expected values
+        let msg_type = MsgType::Tp(Tp::Factory);
+        let length =
+            FactoryHdr::hdr_len() + CmdHdr::hdr_len() + RotHdr::hdr_len();
+        let instr = Cmd::RotY;
+        let options = CmdOpt::empty();
 
+        // Big-endian
         let expected: Vec<u8> = vec![
             // CQC header
             Version::V2 as u8,
@@ -361,30 +570,137 @@ mod request {
             get_byte_32!(length, 1),
             get_byte_32!(length, 2),
             get_byte_32!(length, 3),
+            // FACTORY header
+            num_iter,
+            factory_opt.bits(),
             // CMD header
             get_byte_16!(QUBIT_ID, 0),
             get_byte_16!(QUBIT_ID, 1),
             instr as u8,
             options.bits(),
+            // ROT header
+            STEP,
+        ];
+
+        let encoder = Encoder::new();
+        encoder.encode(&request, &mut buffer[..]);
+        assert_eq!(buffer, expected);
+
+        let decoder = Decoder::new();
+        let decoded: Request = decoder.decode(&buffer[..]).unwrap();
+        assert_eq!(decoded, request);
+
+        let factory_req = decoded.body.get_factory();
+        assert_eq!(factory_req.factory_hdr.num_iter, num_iter);
+        assert_eq!(factory_req.factory_hdr.options, factory_opt);
+    }
+
+    // Wrap a qubit creation command in a factory.
+    #[test]
+    fn factory_cmd_new() {
+        let client = Client::new(APP_ID);
+        let mut cmds =
+            client.cmd_new(QUBIT_ID, CmdOpt::empty()).body.get_cmd();
+        let request = client.factory(10, FactoryOpt::empty(), cmds.remove(0));
+
+        let length = FactoryHdr::hdr_len() + CmdHdr::hdr_len();
+        assert_eq!(request.cqc_hdr.msg_type, MsgType::Tp(Tp::Factory));
+        assert_eq!(request.cqc_hdr.length, length);
+
+        let encoder = Encoder::new();
+        let buffer = encoder.into_vec(&request);
+        assert_eq!(buffer.len() as u32, CqcHdr::hdr_len() + length);
+    }
+
+    // Decode a factory whose length is too short for the Factory Header.
+    // This should return an error (and thus panic on an unwrap).
+    #[test]
+    #[should_panic(expected = "invalid length 1, expected FactoryHdr")]
+    fn factory_hdr_too_short() {
+        let length: u32 = 1;
+        let expected: Vec<u8> = vec![
+            // CQC header
+            Version::V2 as u8,
+            From::from(MsgType::Tp(Tp::Factory)),
+            get_byte_16!(APP_ID, 0),
+            get_byte_16!(APP_ID, 1),
+            get_byte_32!(length, 0),
+            get_byte_32!(length, 1),
+            get_byte_32!(length, 2),
+            get_byte_32!(length, 3),
+            // Truncated FACTORY header
+            10,
         ];
 
         let decoder = Decoder::new();
         let _: Request = decoder.decode(&expected[..]).unwrap();
     }
 
-    // Decode a request that only has an invalid CQC version. This should
-    // return an error (and thus panic on an unwrap).
+    // Decode a factory message with a Factory Header but no command.
     #[test]
-    #[should_panic(expected = "Invalid CQC version")]
-    fn invalid_version() {
-        let msg_type = MsgType::Tp(Tp::Command);
-        let length = CmdHdr::hdr_len();
-        let instr = Cmd::New;
-        let options = *CmdOpt::empty().set_notify().set_block();
+    fn empty_factory_body() {
+        let length = FactoryHdr::hdr_len();
+        let buffer: Vec<u8> = vec![
+            Version::V2 as u8,
+            From::from(MsgType::Tp(Tp::Factory)),
+            get_byte_16!(APP_ID, 0),
+            get_byte_16!(APP_ID, 1),
+            get_byte_32!(length, 0),
+            get_byte_32!(length, 1),
+            get_byte_32!(length, 2),
+            get_byte_32!(length, 3),
+            // Factory header
+            1,
+            FactoryOpt::empty().bits(),
+        ];
+
+        let decoder = Decoder::new();
+        match decoder.validate_bytes(&buffer[..]) {
+            Err(DecodeError::EmptyFactoryBody) => (),
+            other => panic!("Expected EmptyFactoryBody, got {:?}", other),
+        }
+    }
 
+    // Encode a conditional packet comparing two reference IDs.
+    #[test]
+    fn if_hdr() {
+        let client = Client::new(APP_ID);
+        let then = ReqCmd {
+            cmd_hdr: CmdHdr {
+                qubit_id: QUBIT_ID,
+                instr: Cmd::X,
+                options: CmdOpt::empty(),
+            },
+            xtra_hdr: XtraHdr::None,
+        };
+        let request =
+            client.if_ref_cmp_ref(LEFT_REF, CmpType::InEq, RIGHT_REF, then);
+
+        let if_hdr = match request.body {
+            ReqBody::If(ref if_req) => &if_req.if_hdr,
+            _ => panic!("Expected IfReq"),
+        };
+        assert_eq!(if_hdr.left_op, LEFT_REF);
+        assert_eq!(if_hdr.operator, CmpType::InEq);
+        assert_eq!(if_hdr.right_op_t, OpType::RefId);
+        assert_eq!(if_hdr.right_op, RIGHT_REF);
+        assert_eq!(if_hdr.length, CmdHdr::hdr_len());
+
+        // Buffer to write into.
+        let buf_len: usize = request.len() as usize;
+        let mut buffer = vec![0xAA; buf_len];
+
+        // Expected values
+        let msg_type = MsgType::Tp(Tp::If);
+        let length = IfHdr::hdr_len() + CmdHdr::hdr_len();
+        let if_length = CmdHdr::hdr_len();
+        let instr = Cmd::X;
+        let options = CmdOpt::empty();
+
+        // Big-endian
         let expected: Vec<u8> = vec![
             // CQC header
-            Version::V2 as u8 + 1,
+            Version::V2 as u8,
             From::from(msg_type),
             get_byte_16!(APP_ID, 0),
             get_byte_16!(APP_ID, 1),
@@ -392,6 +708,21 @@ mod request {
             get_byte_32!(length, 1),
             get_byte_32!(length, 2),
             get_byte_32!(length, 3),
+            // IF header
+            get_byte_32!(LEFT_REF, 0),
+            get_byte_32!(LEFT_REF, 1),
+            get_byte_32!(LEFT_REF, 2),
+            get_byte_32!(LEFT_REF, 3),
+            CmpType::InEq as u8,
+            OpType::RefId as u8,
+            get_byte_32!(RIGHT_REF, 0),
+            get_byte_32!(RIGHT_REF, 1),
+            get_byte_32!(RIGHT_REF, 2),
+            get_byte_32!(RIGHT_REF, 3),
+            get_byte_32!(if_length, 0),
+            get_byte_32!(if_length, 1),
+            get_byte_32!(if_length, 2),
+            get_byte_32!(if_length, 3),
             // CMD header
             get_byte_16!(QUBIT_ID, 0),
             get_byte_16!(QUBIT_ID, 1),
@@ -399,23 +730,81 @@ mod request {
             options.bits(),
         ];
 
+        let encoder = Encoder::new();
+        encoder.encode(&request, &mut buffer[..]);
+        assert_eq!(buffer, expected);
+
         let decoder = Decoder::new();
-        let _: Request = decoder.decode(&expected[..]).unwrap();
+        let decoded: Request = decoder.decode(&buffer[..]).unwrap();
+        assert_eq!(decoded, request);
     }
 
-    // Decode a request that only has an invalid message type. This should
-    // return an error (and thus panic on an unwrap).
+    // Decode a conditional packet whose If Header announces a longer command
+    // than follows it.
     #[test]
-    #[should_panic(expected = "Invalid CQC message type")]
-    fn invalid_msg_type() {
-        let length = CmdHdr::hdr_len();
-        let instr = Cmd::New;
-        let options = *CmdOpt::empty().set_notify().set_block();
+    fn if_hdr_too_long() {
+        let client = Client::new(APP_ID);
+        let then = ReqCmd {
+            cmd_hdr: CmdHdr {
+                qubit_id: QUBIT_ID,
+                instr: Cmd::X,
+                options: CmdOpt::empty(),
+            },
+            xtra_hdr: XtraHdr::None,
+        };
+        let mut request =
+            client.if_ref_cmp_ref(LEFT_REF, CmpType::Eq, RIGHT_REF, then);
+        if let ReqBody::If(ref mut if_req) = request.body {
+            if_req.if_hdr.length += RotHdr::hdr_len();
+        }
 
-        let expected: Vec<u8> = vec![
+        let encoder = Encoder::new();
+        let buffer = encoder.into_vec(&request);
+
+        let decoder = Decoder::new();
+        assert!(decoder.validate_bytes(&buffer[..]).is_err());
+    }
+
+    // Decode Command, GetTime, and Factory messages without any commands.
+    // This is an error as each of them requires at least a Command Header.
+    #[test]
+    fn empty_cmd_body() {
+        let length = 0;
+        let decoder = Decoder::new();
+
+        for &tp in &[Tp::Command, Tp::GetTime, Tp::Factory] {
+            let buffer: Vec<u8> = vec![
+                Version::V2 as u8,
+                From::from(MsgType::Tp(tp)),
+                get_byte_16!(APP_ID, 0),
+                get_byte_16!(APP_ID, 1),
+                get_byte_32!(length, 0),
+                get_byte_32!(length, 1),
+                get_byte_32!(length, 2),
+                get_byte_32!(length, 3),
+            ];
+
+            match decoder.validate_bytes(&buffer[..]) {
+                Err(DecodeError::EmptyCommandBody(msg_type)) => {
+                    assert_eq!(msg_type, MsgType::Tp(tp))
+                }
+                other => panic!("Expected EmptyCommandBody, got {:?}", other),
+            }
+
+            let err = decoder.decode::<Request>(&buffer[..]).unwrap_err();
+            assert!(err.to_string().contains("empty command body"));
+        }
+    }
+
+    // Validate a hand-built buffer and the same buffer with a corrupt
+    // instruction.
+    #[test]
+    fn validate_bytes() {
+        let length = CmdHdr::hdr_len();
+        let mut buffer: Vec<u8> = vec![
             // CQC header
             Version::V2 as u8,
-            0xFF,
+            From::from(MsgType::Tp(Tp::Command)),
             get_byte_16!(APP_ID, 0),
             get_byte_16!(APP_ID, 1),
             get_byte_32!(length, 0),
@@ -425,11 +814,1470 @@ mod request {
             // CMD header
             get_byte_16!(QUBIT_ID, 0),
             get_byte_16!(QUBIT_ID, 1),
-            instr as u8,
-            options.bits(),
+            Cmd::H as u8,
+            CmdOpt::empty().bits(),
         ];
 
         let decoder = Decoder::new();
-        let _: Request = decoder.decode(&expected[..]).unwrap();
+        assert!(decoder.validate_bytes(&buffer[..]).is_ok());
+
+        buffer[10] = 0xFF;
+        assert!(decoder.validate_bytes(&buffer[..]).is_err());
+    }
+
+    // Measure a register in one request and collect the outcomes.
+    #[test]
+    fn measure_all() {
+        let client = Client::new(APP_ID);
+        let qubit_ids = [QUBIT_ID, EXTRA_QUBIT_ID, 0];
+        let (request, mut collector) =
+            client.measure_all(&qubit_ids, CmdOpt::empty());
+
+        assert_eq!(request.cqc_hdr.msg_type, MsgType::Tp(Tp::Command));
+        assert_eq!(request.cqc_hdr.length, 3 * CmdHdr::hdr_len());
+
+        let encoder = Encoder::new();
+        let buffer = encoder.into_vec(&request);
+        assert_eq!(buffer.len(), request.len() as usize);
+
+        let decoder = Decoder::new();
+        let decoded: Request = decoder.decode(&buffer[..]).unwrap();
+        assert_eq!(decoded, request);
+
+        let cmds = decoded.body.get_cmd();
+        for (req_cmd, &qubit_id) in cmds.iter().zip(qubit_ids.iter()) {
+            assert_eq!(req_cmd.cmd_hdr.qubit_id, qubit_id);
+            assert_eq!(req_cmd.cmd_hdr.instr, Cmd::Measure);
+        }
+
+        let server = Server::new(APP_ID);
+        let outcomes = [MeasOut::One, MeasOut::Zero, MeasOut::One];
+        assert_eq!(collector.push(&server.meas_out(outcomes[0])), None);
+        assert_eq!(collector.push(&server.done()), None);
+        assert_eq!(collector.push(&server.meas_out(outcomes[1])), None);
+        assert!(!collector.is_complete());
+        assert_eq!(
+            collector.push(&server.meas_out(outcomes[2])),
+            Some(outcomes.to_vec())
+        );
+        assert!(collector.is_complete());
+    }
+
+    // Encode a program preparing a three-qubit GHZ state.
+    #[test]
+    fn prepare_ghz() {
+        let client = Client::new(APP_ID);
+        let qubit_ids = [QUBIT_ID, EXTRA_QUBIT_ID, EXTRA_QUBIT_ID + 1];
+        let request = client.prepare_ghz(&qubit_ids, CmdOpt::empty());
+
+        let cmds = request.body.get_cmd();
+        let instrs: Vec<Cmd> =
+            cmds.iter().map(|req_cmd| req_cmd.cmd_hdr.instr).collect();
+        assert_eq!(instrs.iter().filter(|&&i| i == Cmd::H).count(), 1);
+        assert_eq!(instrs.iter().filter(|&&i| i == Cmd::Cnot).count(), 2);
+        assert_eq!(
+            instrs,
+            vec![Cmd::New, Cmd::New, Cmd::New, Cmd::H, Cmd::Cnot, Cmd::Cnot]
+        );
+
+        // The CNOTs form a chain from the first qubit.
+        let chain: Vec<(u16, u16)> = cmds[4..]
+            .iter()
+            .map(|req_cmd| {
+                let target = match req_cmd.xtra_hdr {
+                    XtraHdr::Qubit(ref hdr) => hdr.qubit_id,
+                    _ => panic!("Expected QubitHdr"),
+                };
+                (req_cmd.cmd_hdr.qubit_id, target)
+            })
+            .collect();
+        assert_eq!(
+            chain,
+            vec![(qubit_ids[0], qubit_ids[1]), (qubit_ids[1], qubit_ids[2])]
+        );
+
+        let request = client.prepare_ghz(&qubit_ids, CmdOpt::empty());
+        let encoder = Encoder::new();
+        let buffer = encoder.into_vec(&request);
+        assert_eq!(
+            buffer.len() as u32,
+            CqcHdr::hdr_len()
+                + 6 * CmdHdr::hdr_len()
+                + 2 * QubitHdr::hdr_len()
+        );
+
+        let decoder = Decoder::new();
+        let decoded: Request = decoder.decode(&buffer[..]).unwrap();
+        assert_eq!(decoded, request);
+    }
+
+    // Building and encoding in one call matches the two-step path.
+    #[test]
+    fn cmd_new_bytes() {
+        let client = Client::new(APP_ID);
+        let options = *CmdOpt::empty().set_notify().set_block();
+        let request = client.cmd_new(QUBIT_ID, options);
+
+        let encoder = Encoder::new();
+        let expected = encoder.into_vec(&request);
+
+        assert_eq!(client.cmd_new_bytes(QUBIT_ID, options), expected);
+        assert_eq!(client.encode(request), expected);
+    }
+
+    // Each Pauli maps to its own instruction.
+    #[test]
+    fn cmd_pauli() {
+        let client = Client::new(APP_ID);
+        let paulis = [
+            (Pauli::I, Cmd::I),
+            (Pauli::X, Cmd::X),
+            (Pauli::Y, Cmd::Y),
+            (Pauli::Z, Cmd::Z),
+        ];
+
+        let encoder = Encoder::new();
+        for &(pauli, instr) in paulis.iter() {
+            let request = client.cmd_pauli(QUBIT_ID, pauli, CmdOpt::empty());
+            let buffer = encoder.into_vec(&request);
+            assert_eq!(buffer[10], instr as u8);
+        }
+    }
+
+    // Applying H to a qubit that was never created inserts a New first.
+    #[test]
+    fn program_auto_allocate() {
+        let cmd = |qubit_id, instr| ReqCmd {
+            cmd_hdr: CmdHdr {
+                qubit_id,
+                instr,
+                options: CmdOpt::empty(),
+            },
+            xtra_hdr: XtraHdr::None,
+        };
+        let instrs = |request: Request| -> Vec<(u16, Cmd)> {
+            request
+                .body
+                .get_cmd()
+                .iter()
+                .map(|c| (c.cmd_hdr.qubit_id, c.cmd_hdr.instr))
+                .collect()
+        };
+
+        let request = ProgramBuilder::new(APP_ID)
+            .auto_allocate(true)
+            .push(cmd(QUBIT_ID, Cmd::H))
+            .push(cmd(QUBIT_ID, Cmd::X))
+            .build();
+        assert_eq!(request.cqc_hdr.length, 3 * CmdHdr::hdr_len());
+        assert_eq!(
+            instrs(request),
+            vec![(QUBIT_ID, Cmd::New), (QUBIT_ID, Cmd::H), (QUBIT_ID, Cmd::X)]
+        );
+
+        // Without auto allocation the program is left as is.
+        let request = ProgramBuilder::new(APP_ID)
+            .push(cmd(QUBIT_ID, Cmd::H))
+            .build();
+        assert_eq!(instrs(request), vec![(QUBIT_ID, Cmd::H)]);
+    }
+
+    // The inverse of K is K.
+    #[test]
+    fn cmd_k_inverse() {
+        let client = Client::new(APP_ID);
+        let options = *CmdOpt::empty().set_notify();
+        assert_eq!(
+            client.cmd_k_inverse(QUBIT_ID, options),
+            client.cmd_k(QUBIT_ID, options)
+        );
+    }
+
+    // Build a command for an instruction chosen at runtime.
+    #[test]
+    fn cmd_runtime_instr() {
+        let client = Client::new(APP_ID);
+        let options = CmdOpt::notify_block();
+
+        for &(instr, ref expected) in &[
+            (Cmd::X, client.cmd_x(QUBIT_ID, options)),
+            (Cmd::H, client.cmd_h(QUBIT_ID, options)),
+            (Cmd::Measure, client.cmd_measure(QUBIT_ID, options)),
+        ] {
+            let request = client.cmd(QUBIT_ID, instr, options, XtraHdr::None);
+            assert_eq!(&request, expected);
+        }
+
+        let request = client.cmd(
+            QUBIT_ID,
+            Cmd::RotY,
+            options,
+            XtraHdr::Rot(RotHdr { step: STEP }),
+        );
+        assert_eq!(request, client.cmd_rot_y(QUBIT_ID, options, STEP));
+    }
+
+    // Address several requests to the same remote node.
+    #[test]
+    fn remote_client() {
+        let client = Client::new(APP_ID);
+        let remote_id = RemoteId {
+            remote_app_id: REMOTE_APP_ID,
+            remote_node: REMOTE_NODE,
+            remote_port: REMOTE_PORT,
+        };
+        let remote = client.targeting(remote_id);
+
+        assert_eq!(remote.remote_id(), remote_id);
+        assert_eq!(
+            remote.send(QUBIT_ID, CmdOpt::notify()),
+            client.cmd_send(QUBIT_ID, CmdOpt::notify(), remote_id)
+        );
+        assert_eq!(
+            remote.epr(QUBIT_ID, CmdOpt::empty()),
+            client.cmd_epr(QUBIT_ID, CmdOpt::empty(), remote_id)
+        );
+    }
+
+    // Select the rotation axis at runtime.
+    #[test]
+    fn cmd_rot_axis() {
+        let client = Client::new(APP_ID);
+        let options = CmdOpt::notify();
+
+        assert_eq!(
+            client.cmd_rot(QUBIT_ID, Axis::X, options, STEP),
+            client.cmd_rot_x(QUBIT_ID, options, STEP)
+        );
+        assert_eq!(
+            client.cmd_rot(QUBIT_ID, Axis::Y, options, STEP),
+            client.cmd_rot_y(QUBIT_ID, options, STEP)
+        );
+        assert_eq!(
+            client.cmd_rot(QUBIT_ID, Axis::Z, options, STEP),
+            client.cmd_rot_z(QUBIT_ID, options, STEP)
+        );
+    }
+
+    // Chain several commands with the ACTION option.
+    #[test]
+    fn cmd_sequence() {
+        let client = Client::new(APP_ID);
+        let cmd = |instr: Cmd| ReqCmd {
+            cmd_hdr: CmdHdr {
+                qubit_id: QUBIT_ID,
+                instr,
+                options: CmdOpt::notify(),
+            },
+            xtra_hdr: XtraHdr::None,
+        };
+
+        let request = client
+            .sequence(vec![cmd(Cmd::H), cmd(Cmd::X), cmd(Cmd::Z)])
+            .unwrap();
+        assert_eq!(request.cqc_hdr.msg_type, MsgType::Tp(Tp::Command));
+        assert_eq!(request.cqc_hdr.length, 3 * CmdHdr::hdr_len());
+
+        let options: Vec<CmdOpt> = request
+            .body
+            .get_cmd()
+            .into_iter()
+            .map(|req_cmd| req_cmd.cmd_hdr.options)
+            .collect();
+        let chained = CmdOpt::NOTIFY | CmdOpt::ACTION;
+        assert_eq!(options, vec![chained, chained, CmdOpt::NOTIFY]);
+
+        // The backend rejects a command request without a command.
+        assert_eq!(
+            client.sequence(Vec::new()),
+            Err(BuildError::EmptySequence)
+        );
+    }
+
+    // Decompose the adjoint of T into rotations.
+    #[test]
+    fn cmd_dagger() {
+        let client = Client::new(APP_ID);
+        let rotations = |request: Request| -> Vec<(Cmd, u8)> {
+            request
+                .body
+                .get_mix()
+                .into_iter()
+                .flat_map(|seg| seg.body.get_cmd())
+                .map(|req_cmd| {
+                    let step = req_cmd.xtra_hdr.get_rot_hdr().step;
+                    (req_cmd.cmd_hdr.instr, step)
+                })
+                .collect()
+        };
+
+        let t_dagger = client.cmd_t_dagger(QUBIT_ID, CmdOpt::empty());
+        assert_eq!(t_dagger.cqc_hdr.msg_type, MsgType::Tp(Tp::Mix));
+        assert_eq!(rotations(t_dagger), vec![(Cmd::RotZ, 224)]);
+
+        // K is its own adjoint so it is not decomposed.
+        let options = *CmdOpt::empty().set_notify();
+        assert_eq!(
+            client.cmd_k_dagger(QUBIT_ID, options),
+            client.cmd_k(QUBIT_ID, options)
+        );
+    }
+
+    // A derived client builds requests for the offset application ID.
+    #[test]
+    fn derive() {
+        let client = Client::new(APP_ID).derive(1);
+        let request = client.hello();
+        assert_eq!(request.cqc_hdr.app_id, APP_ID + 1);
+
+        let client = Client::new(u16::MAX).derive(2);
+        assert_eq!(client.hello().cqc_hdr.app_id, 1);
+
+        // The correlation token bits and counter are carried over.
+        let client = Client::with_correlation(APP_ID, 2);
+        client.hello();
+        let derived = client.derive(1);
+        let request = derived.hello();
+        assert_eq!(request.cqc_hdr.app_id, (1 << 14) | (APP_ID + 1));
+
+        // The offset wraps within the bits left by the token.
+        let client = Client::with_correlation(0x3F_FF, 2).derive(2);
+        assert_eq!(client.hello().cqc_hdr.app_id, 1);
+    }
+
+    // A client can be shared between threads and every request still gets
+    // its own correlation token.
+    #[test]
+    fn client_shared_between_threads() {
+        let client = Client::with_correlation(APP_ID, 2);
+        let mut app_ids: Vec<u16> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| client.hello().cqc_hdr.app_id))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        app_ids.sort();
+
+        let expected: Vec<u16> =
+            (0..4).map(|token| (token << 14) | APP_ID).collect();
+        assert_eq!(app_ids, expected);
+    }
+
+    // Build a Mix segment holding an if request on the given qubit.
+    fn if_seg(qubit_id: u16) -> MixSeg {
+        let req_cmd = ReqCmd {
+            cmd_hdr: CmdHdr {
+                qubit_id,
+                instr: Cmd::X,
+                options: CmdOpt::empty(),
+            },
+            xtra_hdr: XtraHdr::None,
+        };
+        MixSeg {
+            type_hdr: TypeHdr {
+                hdr_type: Tp::If,
+                length: IfHdr::hdr_len() + CmdHdr::hdr_len(),
+            },
+            body: ReqBody::If(cqc::IfReq {
+                if_hdr: IfHdr {
+                    left_op: LEFT_REF,
+                    operator: CmpType::Eq,
+                    right_op_t: OpType::RefId,
+                    right_op: RIGHT_REF,
+                    length: CmdHdr::hdr_len(),
+                },
+                req_cmd,
+            }),
+        }
+    }
+
+    // Encode and decode a Mix program containing two if requests.
+    #[test]
+    fn mix_if() {
+        let body =
+            ReqBody::Mix(vec![if_seg(QUBIT_ID), if_seg(EXTRA_QUBIT_ID)]);
+        let request = Request {
+            cqc_hdr: CqcHdr {
+                version: Version::V2,
+                msg_type: MsgType::Tp(Tp::Mix),
+                app_id: APP_ID,
+                length: body.len(),
+            },
+            body,
+        };
+
+        let seg_len =
+            TypeHdr::hdr_len() + IfHdr::hdr_len() + CmdHdr::hdr_len();
+        assert_eq!(request.len(), CqcHdr::hdr_len() + 2 * seg_len);
+
+        let mut buffer = vec![0xAA; request.len() as usize];
+        let encoder = Encoder::new();
+        encoder.encode(&request, &mut buffer[..]);
+
+        // The second Type Header follows the first segment.
+        let offset = (CqcHdr::hdr_len() + seg_len) as usize;
+        assert_eq!(buffer[offset], Tp::If as u8);
+
+        let decoder = Decoder::new();
+        let decoded: Request = decoder.decode(&buffer[..]).unwrap();
+        assert_eq!(decoded, request);
+    }
+
+    // Build a Mix program with a command and a conditional segment.
+    #[test]
+    fn mix_builder() {
+        let client = Client::new(APP_ID);
+        let measure = || {
+            client
+                .cmd_measure(QUBIT_ID, CmdOpt::empty())
+                .body
+                .get_cmd()
+                .remove(0)
+        };
+        let flip = client
+            .cmd_x(EXTRA_QUBIT_ID, CmdOpt::empty())
+            .body
+            .get_cmd()
+            .remove(0);
+        let if_hdr = IfHdr {
+            left_op: LEFT_REF,
+            operator: CmpType::Eq,
+            right_op_t: OpType::RefId,
+            right_op: RIGHT_REF,
+            length: 0,
+        };
+
+        // A measurement in a Mix program needs an Assign Header.
+        let err = client
+            .mix()
+            .push_command(measure())
+            .try_build()
+            .unwrap_err();
+        assert_eq!(err, BuildError::MissingAssignHdr(Cmd::Measure));
+
+        let mut measure = measure();
+        measure.xtra_hdr = XtraHdr::Assign(AssignHdr { ref_id: LEFT_REF });
+        let request = client
+            .mix()
+            .push_command(measure)
+            .push_if(if_hdr, flip)
+            .build();
+        assert_eq!(request.cqc_hdr.msg_type, MsgType::Tp(Tp::Mix));
+
+        let mut segs = request.body.get_mix();
+        let cmd_len = CmdHdr::hdr_len();
+        assert_eq!(segs.len(), 2);
+        assert_eq!(segs[0].type_hdr.hdr_type, Tp::Command);
+        assert_eq!(segs[0].type_hdr.length, cmd_len + AssignHdr::hdr_len());
+        assert_eq!(segs[1].type_hdr.hdr_type, Tp::If);
+        assert_eq!(segs[1].type_hdr.length, IfHdr::hdr_len() + cmd_len);
+        let if_req = segs.pop().unwrap().body.get_if();
+        assert_eq!(if_req.if_hdr.length, cmd_len);
+    }
+
+    // Mix programs built by the builder survive an encode/decode round trip.
+    #[test]
+    fn mix_builder_round_trip() {
+        let client = Client::new(APP_ID);
+        let then = client
+            .cmd_i(QUBIT_ID, CmdOpt::empty())
+            .body
+            .get_cmd()
+            .remove(0);
+        let if_hdr = IfHdr {
+            left_op: LEFT_REF,
+            operator: CmpType::InEq,
+            right_op_t: OpType::Value,
+            right_op: 1,
+            length: 0,
+        };
+        let request = client.mix().push_if(if_hdr, then).build();
+
+        let encoder = Encoder::new();
+        let buffer = encoder.into_vec(&request);
+        assert_eq!(buffer.len(), request.len() as usize);
+
+        let decoder = Decoder::new();
+        let decoded: Request = decoder.decode(&buffer[..]).unwrap();
+        assert_eq!(decoded, request);
+    }
+
+    // A Mix program that measures a qubit and branches on the outcome
+    // survives an encode/decode round trip and validates.
+    #[test]
+    fn mix_measure_round_trip() {
+        let client = Client::new(APP_ID);
+        let measure = || {
+            let mut measure = client
+                .cmd_measure(QUBIT_ID, CmdOpt::empty())
+                .body
+                .get_cmd()
+                .remove(0);
+            measure.xtra_hdr = XtraHdr::Assign(AssignHdr { ref_id: LEFT_REF });
+            measure
+        };
+        let flip = client
+            .cmd_x(EXTRA_QUBIT_ID, CmdOpt::empty())
+            .body
+            .get_cmd()
+            .remove(0);
+        let if_hdr = IfHdr {
+            left_op: LEFT_REF,
+            operator: CmpType::Eq,
+            right_op_t: OpType::Value,
+            right_op: 1,
+            length: 0,
+        };
+        let request = client
+            .mix()
+            .push_command(measure())
+            .push_if(if_hdr, flip)
+            .build();
+        assert_eq!(request.validate(), Ok(()));
+
+        let encoder = Encoder::new();
+        let buffer = encoder.into_vec(&request);
+        assert_eq!(buffer.len(), request.len() as usize);
+
+        let decoder = Decoder::new();
+        let decoded: Request = decoder.decode(&buffer[..]).unwrap();
+        assert_eq!(decoded, request);
+
+        // The Assign Header is only read in a Mix program.
+        assert_eq!(
+            measure().validate(),
+            Err(ValidationError::XtraHdrMismatch(Cmd::Measure))
+        );
+    }
+
+    // Decode a Mix program whose second Type Header announces more bytes than
+    // are present.
+    #[test]
+    fn mix_type_hdr_overrun() {
+        let body = ReqBody::Mix(vec![if_seg(QUBIT_ID), if_seg(QUBIT_ID)]);
+        let request = Request {
+            cqc_hdr: CqcHdr {
+                version: Version::V2,
+                msg_type: MsgType::Tp(Tp::Mix),
+                app_id: APP_ID,
+                length: body.len(),
+            },
+            body,
+        };
+
+        let encoder = Encoder::new();
+        let mut buffer = encoder.into_vec(&request);
+
+        // Claim four more bytes in the last Type Header.
+        let seg_len =
+            TypeHdr::hdr_len() + IfHdr::hdr_len() + CmdHdr::hdr_len();
+        let offset = (CqcHdr::hdr_len() + seg_len) as usize;
+        let declared = IfHdr::hdr_len() + CmdHdr::hdr_len() + 4;
+        for byte in 0..4 {
+            buffer[offset + 1 + byte] = get_byte_32!(declared, byte);
+        }
+
+        let decoder = Decoder::new();
+        match decoder.validate_bytes(&buffer[..]) {
+            Err(DecodeError::TypeHeaderOverrun {
+                declared: d,
+                remaining,
+            }) => {
+                assert_eq!(d, declared);
+                assert_eq!(remaining, declared - 4);
+            }
+            other => panic!("Expected TypeHeaderOverrun, got {:?}", other),
+        }
+    }
+
+    // Decode a Mix program where a Type Header announces another Mix.  This
+    // should return an error (and thus panic on an unwrap).
+    #[test]
+    #[should_panic(expected = "may not announce Mix")]
+    fn mix_in_mix() {
+        let mut nested = if_seg(EXTRA_QUBIT_ID);
+        nested.type_hdr.hdr_type = Tp::Mix;
+
+        let body =
+            ReqBody::Mix(vec![if_seg(QUBIT_ID), if_seg(QUBIT_ID), nested]);
+        let request = Request {
+            cqc_hdr: CqcHdr {
+                version: Version::V2,
+                msg_type: MsgType::Tp(Tp::Mix),
+                app_id: APP_ID,
+                length: body.len(),
+            },
+            body,
+        };
+
+        let mut buffer = vec![0xAA; request.len() as usize];
+        let encoder = Encoder::new();
+        encoder.encode(&request, &mut buffer[..]);
+
+        let decoder = Decoder::new();
+        let _: Request = decoder.decode(&buffer[..]).unwrap();
+    }
+
+    // Test an encoding when the provided buffer is too small (should panic).
+    #[test]
+    #[should_panic(expected = "failed to write whole buffer")]
+    fn cqc_hdr_buf_too_small() {
+        let client = Client::new(APP_ID);
+        let request = client.hello();
+
+        // Buffer to write into.
+        let mut buffer = vec![0xAA; (request.len() - 1) as usize];
+
+        let encoder = Encoder::new();
+
+        // This should panic.
+        encoder.encode(&request, &mut buffer[..]);
+    }
+
+    // Test an encoding when the provided buffer is too small, but sufficient
+    // for the CQC header (should panic).
+    #[test]
+    #[should_panic(expected = "failed to write whole buffer")]
+    fn cmd_hdr_buf_too_small() {
+        let client = Client::new(APP_ID);
+        let request = client.cmd_i(QUBIT_ID, CmdOpt::empty());
+
+        // Buffer to write into.
+        let mut buffer = vec![0xAA; (request.len() - 1) as usize];
+
+        let encoder = Encoder::new();
+
+        // This should panic.
+        encoder.encode(&request, &mut buffer[..]);
+    }
+
+    // Decode two concatenated requests one after the other.
+    #[test]
+    fn decode_with_len() {
+        let client = Client::new(APP_ID);
+        let first = client.hello();
+        let second = client.cmd_rot_x(QUBIT_ID, CmdOpt::empty(), STEP);
+
+        let encoder = Encoder::new();
+        let mut buffer = encoder.into_vec(&first);
+        encoder.append_to_vec(&second, &mut buffer);
+
+        let decoder = Decoder::new();
+        let (decoded, consumed): (Request, usize) =
+            decoder.decode_with_len(&buffer[..]).unwrap();
+        assert_eq!(decoded, first);
+        assert_eq!(consumed, first.len() as usize);
+
+        let (decoded, rest): (Request, usize) =
+            decoder.decode_with_len(&buffer[consumed..]).unwrap();
+        assert_eq!(decoded, second);
+        assert_eq!(consumed + rest, buffer.len());
+    }
+
+    // A strict decode rejects anything after the packet.
+    #[test]
+    fn decode_exact() {
+        let client = Client::new(APP_ID);
+        let first = client.hello();
+        let second = client.cmd_rot_x(QUBIT_ID, CmdOpt::empty(), STEP);
+
+        let encoder = Encoder::new();
+        let mut buffer = encoder.into_vec(&first);
+        let len = buffer.len();
+        encoder.append_to_vec(&second, &mut buffer);
+
+        let decoder = Decoder::new();
+        let decoded: Request = decoder.decode_exact(&buffer[..len]).unwrap();
+        assert_eq!(decoded, first);
+
+        let err = decoder.decode_exact::<Request>(&buffer[..]).unwrap_err();
+        match err {
+            DecodeError::TrailingBytes { packet_len, got } => {
+                assert_eq!(packet_len, len);
+                assert_eq!(got, buffer.len());
+            }
+            other => panic!("Expected TrailingBytes, got {:?}", other),
+        }
+    }
+
+    // A buffer that ends inside the packet reports how much is missing.
+    #[test]
+    fn truncated() {
+        let client = Client::new(APP_ID);
+        let request = client.cmd_rot_x(QUBIT_ID, CmdOpt::empty(), STEP);
+        let buffer = Encoder::new().into_vec(&request);
+        let decoder = Decoder::new();
+
+        for &end in &[CqcHdr::hdr_len() as usize - 1, buffer.len() - 1] {
+            let needed = if end < CqcHdr::hdr_len() as usize {
+                CqcHdr::hdr_len() as usize
+            } else {
+                buffer.len()
+            };
+
+            match decoder.decode::<Request>(&buffer[..end]) {
+                Err(DecodeError::Truncated { needed: n, got }) => {
+                    assert_eq!(n, needed);
+                    assert_eq!(got, end);
+                }
+                other => panic!("Expected Truncated, got {:?}", other),
+            }
+        }
+    }
+
+    // Decode the CQC Header without the body.
+    #[test]
+    fn decode_cqc_hdr() {
+        let client = Client::new(APP_ID);
+        let request = client.cmd_rot_x(QUBIT_ID, CmdOpt::empty(), STEP);
+        let buffer = Encoder::new().into_vec(&request);
+        let decoder = Decoder::new();
+
+        let cqc_hdr = decoder.decode_cqc_hdr(&buffer[..8]).unwrap();
+        assert_eq!(cqc_hdr, request.cqc_hdr);
+
+        match decoder.decode_cqc_hdr(&buffer[..7]) {
+            Err(DecodeError::Truncated { needed: 8, got: 7 }) => (),
+            other => panic!("Expected Truncated, got {:?}", other),
+        }
+    }
+
+    // A CQC Header announcing more bytes than the buffer holds.
+    #[test]
+    fn truncated_length() {
+        let client = Client::new(APP_ID);
+        let mut buffer = Encoder::new().into_vec(&client.hello());
+        buffer.extend_from_slice(&[0; 10]);
+        let decoder = Decoder::new();
+
+        for &length in &[40, u32::MAX] {
+            buffer[4..8].copy_from_slice(&length.to_be_bytes());
+            match decoder.decode::<Request>(&buffer) {
+                Err(DecodeError::Truncated { needed, got }) => {
+                    assert_eq!(
+                        needed,
+                        CqcHdr::hdr_len() as usize + length as usize
+                    );
+                    assert_eq!(got, buffer.len());
+                }
+                other => panic!("Expected Truncated, got {:?}", other),
+            }
+        }
+    }
+
+    // The byte order of multi-byte fields depends on the encoder.
+    #[test]
+    fn little_endian() {
+        let client = Client::new(APP_ID);
+        let request = client.cmd_i(QUBIT_ID, CmdOpt::empty());
+
+        let big = Encoder::new().into_vec(&request);
+        let little = Encoder::little_endian().into_vec(&request);
+        assert_eq!(big.len(), little.len());
+
+        assert_eq!(
+            big[2..4],
+            [get_byte_16!(APP_ID, 0), get_byte_16!(APP_ID, 1)]
+        );
+        assert_eq!(
+            little[2..4],
+            [get_byte_16!(APP_ID, 1), get_byte_16!(APP_ID, 0)]
+        );
+        assert_ne!(big[2..4], little[2..4]);
+
+        let decoded: Request =
+            Decoder::little_endian().decode(&little[..]).unwrap();
+        assert_eq!(decoded, request);
+    }
+
+    // A legacy little endian packet is rejected by the big endian decoder.
+    #[test]
+    fn legacy_little_endian() {
+        let client = Client::new(APP_ID);
+        let request = client.cmd_i(QUBIT_ID, CmdOpt::empty());
+
+        let buffer = Encoder::legacy_little_endian().into_vec(&request);
+
+        let decoded: Request =
+            Decoder::legacy_little_endian().decode(&buffer[..]).unwrap();
+        assert_eq!(decoded, request);
+
+        let decoded: Result<Request, _> = Decoder::new().decode(&buffer[..]);
+        assert!(decoded.is_err());
+    }
+
+    // A user gate type that converts into a request.
+    struct Flip(u16);
+
+    impl ToRequest for Flip {
+        fn to_request(&self, client: &Client) -> Request {
+            client.cmd_x(self.0, CmdOpt::empty())
+        }
+    }
+
+    // Encode a user type through the encoder.
+    #[test]
+    fn encode_as() {
+        let client = Client::new(APP_ID);
+        let flip = Flip(QUBIT_ID);
+        let request = flip.to_request(&client);
+
+        let mut buffer = vec![0xAA; request.len() as usize];
+        let encoder = Encoder::new();
+        encoder.encode_as(&flip, &client, &mut buffer[..]);
+        assert_eq!(buffer, encoder.into_vec(&request));
+    }
+
+    // Feed a packet to the decoder one byte at a time.
+    #[test]
+    fn decode_partial() {
+        let client = Client::new(APP_ID);
+        let request = client.cmd_rot_x(QUBIT_ID, CmdOpt::empty(), STEP);
+
+        let encoder = Encoder::new();
+        let mut buffer = encoder.into_vec(&request);
+        encoder.append_to_vec(&client.hello(), &mut buffer);
+
+        let decoder = Decoder::new();
+        let len = request.len() as usize;
+        for end in 0..len {
+            let partial: Option<(Request, usize)> =
+                decoder.decode_partial(&buffer[..end]).unwrap();
+            assert!(partial.is_none());
+        }
+
+        let (decoded, consumed): (Request, usize) =
+            decoder.decode_partial(&buffer[..]).unwrap().unwrap();
+        assert_eq!(decoded, request);
+        assert_eq!(consumed, len);
+    }
+
+    // Read packets one at a time from a reader.
+    #[test]
+    fn decode_from() {
+        let client = Client::new(APP_ID);
+        let first = client.cmd_rot_x(QUBIT_ID, CmdOpt::empty(), STEP);
+        let second = client.hello();
+
+        let encoder = Encoder::new();
+        let mut buffer = encoder.into_vec(&first);
+        encoder.append_to_vec(&second, &mut buffer);
+
+        let decoder = Decoder::new();
+        let mut reader = &buffer[..];
+        let decoded: Request = decoder.decode_from(&mut reader).unwrap();
+        assert_eq!(decoded, first);
+        let decoded: Request = decoder.decode_from(&mut reader).unwrap();
+        assert_eq!(decoded, second);
+        assert!(reader.is_empty());
+
+        // A truncated packet cannot be read.
+        let mut reader = &buffer[..first.len() as usize - 1];
+        let decoded: Result<Request, _> = decoder.decode_from(&mut reader);
+        assert!(decoded.is_err());
+
+        // A corrupt length is not trusted to size the buffer.
+        let mut packet = encoder.into_vec(&first);
+        packet[4..8].copy_from_slice(&[0xFF; 4]);
+        let mut reader = &packet[..];
+        let decoded: Result<Request, _> = decoder.decode_from(&mut reader);
+        assert!(decoded.is_err());
+    }
+
+    // Split a stream into whole packets without decoding them.
+    #[test]
+    fn read_packet() {
+        let client = Client::new(APP_ID);
+        let first = client.cmd_rot_x(QUBIT_ID, CmdOpt::empty(), STEP);
+        let second = client.hello();
+
+        let encoder = Encoder::new();
+        let mut buffer = encoder.into_vec(&first);
+        encoder.append_to_vec(&second, &mut buffer);
+
+        let framer = Framer::new();
+        let mut reader = &buffer[..];
+        let packet = framer.read_packet(&mut reader).unwrap();
+        assert_eq!(packet, encoder.into_vec(&first));
+        let packet = framer.read_packet(&mut reader).unwrap();
+        assert_eq!(packet, encoder.into_vec(&second));
+        assert!(reader.is_empty());
+
+        // A truncated packet cannot be read.
+        let mut reader = &buffer[..first.len() as usize - 1];
+        let err = framer.read_packet(&mut reader).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        // Neither can a packet with an invalid CQC Header.
+        let mut reader = &[0xFF; 8][..];
+        let err = framer.read_packet(&mut reader).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    // Decode requests and responses captured on the same stream.
+    #[test]
+    fn decode_any_stream() {
+        let client = Client::new(APP_ID);
+        let server = Server::new(APP_ID);
+        let request = client.cmd_new(QUBIT_ID, CmdOpt::NOTIFY);
+        let response = server.new_ok(QUBIT_ID);
+
+        let encoder = Encoder::new();
+        let mut buffer = encoder.into_vec(&request);
+        encoder.append_to_vec(&response, &mut buffer);
+
+        let decoder = Decoder::new();
+        let packets: Vec<CqcPacket> = decoder
+            .decode_any_stream(&buffer[..])
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            packets,
+            vec![CqcPacket::Request(request), CqcPacket::Response(response)]
+        );
+
+        // Factory packets are sent in both directions.
+        let request = client.factory(
+            2,
+            FactoryOpt::empty(),
+            ReqCmd {
+                cmd_hdr: CmdHdr {
+                    qubit_id: QUBIT_ID,
+                    instr: Cmd::I,
+                    options: CmdOpt::empty(),
+                },
+                xtra_hdr: XtraHdr::None,
+            },
+        );
+        let response = server.factory(vec![
+            server.new_ok(QUBIT_ID),
+            server.meas_out(MeasOut::One),
+        ]);
+        let mut stream = encoder.into_vec(&request);
+        encoder.append_to_vec(&response, &mut stream);
+
+        let packets: Vec<CqcPacket> = decoder
+            .decode_any_stream(&stream[..])
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            packets,
+            vec![CqcPacket::Request(request), CqcPacket::Response(response)]
+        );
+
+        // Iteration stops at a truncated packet.
+        let end = buffer.len() - 1;
+        let mut packets = decoder.decode_any_stream(&buffer[..end]);
+        assert!(packets.next().unwrap().is_ok());
+        assert!(packets.next().unwrap().is_err());
+        assert!(packets.next().is_none());
+    }
+
+    // A fallible encoding reports the shortfall instead of panicking.
+    #[test]
+    fn try_encode() {
+        let client = Client::new(APP_ID);
+        let request = client.cmd_i(QUBIT_ID, CmdOpt::empty());
+        let encoder = Encoder::new();
+
+        let mut buffer = vec![0xAA; (request.len() - 1) as usize];
+        match encoder.try_encode(&request, &mut buffer[..]) {
+            Err(EncodeError::BufferTooSmall { needed, got }) => {
+                assert_eq!(needed, request.len() as usize);
+                assert_eq!(got, buffer.len());
+            }
+            other => panic!("Expected BufferTooSmall, got {:?}", other),
+        }
+        assert!(buffer.iter().all(|&byte| byte == 0xAA));
+
+        let mut buffer = vec![0xAA; (request.len() + 1) as usize];
+        let written = encoder.try_encode(&request, &mut buffer[..]).unwrap();
+        assert_eq!(written, request.len() as usize);
+        assert_eq!(&buffer[..written], &encoder.into_vec(&request)[..]);
+    }
+
+    // Size a buffer for any serializable value before encoding it.
+    #[test]
+    fn encoded_len() {
+        let client = Client::new(APP_ID);
+        let request = client.cmd_rot_x(QUBIT_ID, CmdOpt::empty(), STEP);
+        let encoder = Encoder::new();
+        assert_eq!(encoder.encoded_len(&request), u64::from(request.len()));
+
+        let rsp = Server::new(APP_ID).new_ok(QUBIT_ID);
+        assert_eq!(encoder.encoded_len(&rsp), u64::from(rsp.len()));
+
+        let qubit_hdr = QubitHdr { qubit_id: QUBIT_ID };
+        let mut buffer = vec![0; encoder.encoded_len(&qubit_hdr) as usize];
+        encoder.encode(&qubit_hdr, &mut buffer[..]);
+        assert_eq!(
+            buffer,
+            vec![get_byte_16!(QUBIT_ID, 0), get_byte_16!(QUBIT_ID, 1)]
+        );
+    }
+
+    // Test an encoding when the provided buffer is too large.  Excess should
+    // be untouched.
+    #[test]
+    fn buf_too_large() {
+        let client = Client::new(APP_ID);
+        let request = client.hello();
+
+        // Buffer to write into.
+        let write_len: usize = request.len() as usize;
+        let buf_len: usize = write_len + 4;
+        let mut buffer = vec![0xAA; buf_len];
+
+        // Expected values
+        let msg_type = MsgType::Tp(Tp::Hello);
+        let length = 0;
+
+        // Big-endian
+        let expected: Vec<u8> = vec![
+            Version::V2 as u8,
+            From::from(msg_type),
+            get_byte_16!(APP_ID, 0),
+            get_byte_16!(APP_ID, 1),
+            get_byte_32!(length, 0),
+            get_byte_32!(length, 1),
+            get_byte_32!(length, 2),
+            get_byte_32!(length, 3),
+            // The rest should be untouched.
+            0xAA,
+            0xAA,
+            0xAA,
+            0xAA,
+        ];
+
+        let encoder = Encoder::new();
+        encoder.encode(&request, &mut buffer[..]);
+        assert_eq!(buffer, expected);
+
+        let decoder = Decoder::new();
+        let decoded: Request = decoder.decode(&buffer[..]).unwrap();
+        assert_eq!(decoded, request);
+    }
+
+    // Append two requests to the same vector and decode them both back.
+    #[test]
+    fn append_to_vec() {
+        let client = Client::new(APP_ID);
+        let first = client.hello();
+        let second = client.cmd_rot_x(QUBIT_ID, CmdOpt::empty(), STEP);
+
+        let encoder = Encoder::new();
+        let mut buffer: Vec<u8> = Vec::new();
+        let first_len = encoder.append_to_vec(&first, &mut buffer);
+        let second_len = encoder.append_to_vec(&second, &mut buffer);
+
+        assert_eq!(first_len, first.len() as usize);
+        assert_eq!(second_len, second.len() as usize);
+        assert_eq!(buffer.len(), first_len + second_len);
+
+        let decoder = Decoder::new();
+        let decoded: Request = decoder.decode(&buffer[..first_len]).unwrap();
+        assert_eq!(decoded, first);
+        let decoded: Request = decoder.decode(&buffer[first_len..]).unwrap();
+        assert_eq!(decoded, second);
+    }
+
+    // Encode a request directly into a writer.
+    #[test]
+    fn encode_into() {
+        let client = Client::new(APP_ID);
+        let request = client.cmd_rot_x(QUBIT_ID, CmdOpt::empty(), STEP);
+
+        let encoder = Encoder::new();
+        let mut writer: Vec<u8> = Vec::new();
+        encoder.encode_into(&request, &mut writer).unwrap();
+        assert_eq!(writer, encoder.into_vec(&request));
+
+        // A writer without room reports an error.
+        let mut buffer = [0u8; 4];
+        assert!(encoder.encode_into(&request, &mut buffer[..]).is_err());
+    }
+
+    // Encode a program split over several requests one packet at a time.
+    #[test]
+    fn chunked_encode() {
+        let client = Client::new(APP_ID);
+        let requests = vec![
+            client.cmd_new(QUBIT_ID, CmdOpt::empty()),
+            client.cmd_rot_x(QUBIT_ID, CmdOpt::empty(), STEP),
+            client.cmd_measure(QUBIT_ID, CmdOpt::empty()),
+        ];
+
+        let encoder = Encoder::new();
+        let chunks: Vec<Vec<u8>> = encoder.chunked_encode(&requests).collect();
+        assert_eq!(chunks.len(), requests.len());
+
+        let decoder = Decoder::new();
+        for (chunk, request) in chunks.iter().zip(&requests) {
+            assert_eq!(chunk.len(), request.len() as usize);
+            let decoded: Request = decoder.decode(&chunk[..]).unwrap();
+            assert_eq!(&decoded, request);
+        }
+    }
+
+    // Encode requests with bodies other than a plain command into buffers of
+    // exactly `request.len()` bytes.
+    #[test]
+    fn exact_len() {
+        let client = Client::new(APP_ID);
+        let req_cmd = ReqCmd {
+            cmd_hdr: CmdHdr {
+                qubit_id: QUBIT_ID,
+                instr: Cmd::RotX,
+                options: CmdOpt::empty(),
+            },
+            xtra_hdr: XtraHdr::Rot(RotHdr { step: STEP }),
+        };
+
+        let requests = [
+            client.get_time(QUBIT_ID),
+            client.factory(10, *FactoryOpt::empty().set_notify(), req_cmd),
+        ];
+        let lengths = [
+            CmdHdr::hdr_len(),
+            FactoryHdr::hdr_len() + CmdHdr::hdr_len() + RotHdr::hdr_len(),
+        ];
+
+        let encoder = Encoder::new();
+        for (request, &length) in requests.iter().zip(lengths.iter()) {
+            assert_eq!(request.cqc_hdr.length, length);
+            assert_eq!(request.len(), CqcHdr::hdr_len() + length);
+
+            let mut buffer = vec![0xAA; request.len() as usize];
+            encoder.encode(request, &mut buffer[..]);
+            assert_eq!(buffer, encoder.into_vec(request));
+        }
+    }
+
+    // Diff two requests that only differ in the qubit ID.
+    #[test]
+    fn diff() {
+        let client = Client::new(APP_ID);
+        let request = client.cmd_h(QUBIT_ID, CmdOpt::empty());
+        let other = client.cmd_h(EXTRA_QUBIT_ID, CmdOpt::empty());
+
+        assert_eq!(request.diff(&request), vec![]);
+        assert_eq!(request.diff(&other), vec![FieldDiff::QubitId(0)]);
+
+        let other = client.cmd_x(QUBIT_ID, *CmdOpt::empty().set_notify());
+        assert_eq!(
+            request.diff(&other),
+            vec![FieldDiff::Instr(0), FieldDiff::Options(0)]
+        );
+
+        let other = client.hello();
+        assert_eq!(
+            request.diff(&other),
+            vec![FieldDiff::MsgType, FieldDiff::Length, FieldDiff::Body]
+        );
+    }
+
+    // The spans of a two-command list are contiguous and cover the body.
+    #[test]
+    fn command_spans() {
+        let client = Client::new(APP_ID);
+        let remote_id = RemoteId {
+            remote_app_id: REMOTE_APP_ID,
+            remote_node: REMOTE_NODE,
+            remote_port: REMOTE_PORT,
+        };
+        let mut request = client.cmd_h(QUBIT_ID, CmdOpt::empty());
+        let send = client.cmd_send(QUBIT_ID, CmdOpt::empty(), remote_id);
+        request.body = ReqBody::Cmd(vec![
+            request.body.get_cmd().remove(0),
+            send.body.get_cmd().remove(0),
+        ]);
+        request.cqc_hdr.length = request.body.len();
+
+        let hdr_len = CqcHdr::hdr_len() as usize;
+        let cmd_len = CmdHdr::hdr_len() as usize;
+        let comm_len = CommHdr::hdr_len() as usize;
+        let spans = request.command_spans();
+        assert_eq!(
+            spans,
+            vec![
+                hdr_len..hdr_len + cmd_len,
+                hdr_len + cmd_len..hdr_len + 2 * cmd_len + comm_len,
+            ]
+        );
+        assert_eq!(spans[1].end, request.len() as usize);
+
+        // The spans index the commands in the encoded packet.
+        let encoder = Encoder::new();
+        let buffer = encoder.into_vec(&request);
+        assert_eq!(buffer[spans[0].start + 2], Cmd::H as u8);
+        assert_eq!(buffer[spans[1].start + 2], Cmd::Send as u8);
+    }
+
+    // List the headers of a send command.
+    #[test]
+    fn headers() {
+        let client = Client::new(APP_ID);
+        let request = client.cmd_send(
+            QUBIT_ID,
+            CmdOpt::empty(),
+            RemoteId {
+                remote_app_id: REMOTE_APP_ID,
+                remote_node: REMOTE_NODE,
+                remote_port: REMOTE_PORT,
+            },
+        );
+
+        assert_eq!(
+            request.headers(),
+            vec![HeaderKind::Cqc, HeaderKind::Cmd, HeaderKind::Comm]
+        );
+    }
+
+    // Validate a request with a wrong length and one with a mismatched extra
+    // header.
+    #[test]
+    fn request_validate() {
+        let client = Client::new(APP_ID);
+        let request = client.cmd_send(
+            QUBIT_ID,
+            CmdOpt::empty(),
+            RemoteId {
+                remote_app_id: REMOTE_APP_ID,
+                remote_node: REMOTE_NODE,
+                remote_port: REMOTE_PORT,
+            },
+        );
+        assert_eq!(request.validate(), Ok(()));
+
+        let mut wrong_len = client.cmd_i(QUBIT_ID, CmdOpt::empty());
+        wrong_len.cqc_hdr.length += 1;
+        assert_eq!(
+            wrong_len.validate(),
+            Err(ValidationError::LengthMismatch {
+                declared: CmdHdr::hdr_len() + 1,
+                actual: CmdHdr::hdr_len(),
+            })
+        );
+
+        let mut no_comm = client.cmd_i(QUBIT_ID, CmdOpt::empty());
+        if let ReqBody::Cmd(ref mut cmds) = no_comm.body {
+            cmds[0].cmd_hdr.instr = Cmd::Send;
+        }
+        assert_eq!(
+            no_comm.validate(),
+            Err(ValidationError::XtraHdrMismatch(Cmd::Send))
+        );
+    }
+
+    // A rotation header on a command that is not a rotation is rejected.
+    #[test]
+    fn validate_seq_hdr() {
+        let client = Client::new(APP_ID);
+        let seq_hdr = || XtraHdr::Seq(SeqHdr { cmd_length: 4 });
+
+        let request = client.cmd(QUBIT_ID, Cmd::H, CmdOpt::ACTION, seq_hdr());
+        assert_eq!(request.cqc_hdr.length, CmdHdr::hdr_len() + 1);
+        assert_eq!(
+            request.headers(),
+            vec![HeaderKind::Cqc, HeaderKind::Cmd, HeaderKind::Seq]
+        );
+        assert_eq!(request.validate(), Ok(()));
+
+        let request = client.cmd(QUBIT_ID, Cmd::H, CmdOpt::empty(), seq_hdr());
+        assert_eq!(
+            request.validate(),
+            Err(ValidationError::XtraHdrMismatch(Cmd::H))
+        );
+
+        let request =
+            client.cmd(QUBIT_ID, Cmd::RotX, CmdOpt::ACTION, seq_hdr());
+        assert_eq!(
+            request.validate(),
+            Err(ValidationError::XtraHdrMismatch(Cmd::RotX))
+        );
+    }
+
+    #[test]
+    fn validate_rot_on_x() {
+        let client = Client::new(APP_ID);
+        let mut request = client.cmd_rot_x(QUBIT_ID, CmdOpt::empty(), STEP);
+        if let ReqBody::Cmd(ref mut cmds) = request.body {
+            cmds[0].cmd_hdr.instr = Cmd::X;
+        }
+
+        assert_eq!(
+            request.validate(),
+            Err(ValidationError::XtraHdrMismatch(Cmd::X))
+        );
+    }
+
+    // Validate a factory repeating a CNOT gate.
+    #[test]
+    fn factory_validate() {
+        let factory_hdr = FactoryHdr {
+            num_iter: 10,
+            options: FactoryOpt::empty(),
+        };
+        let cmd_hdr = CmdHdr {
+            qubit_id: QUBIT_ID,
+            instr: Cmd::Cnot,
+            options: CmdOpt::empty(),
+        };
+
+        let valid = FactoryReq {
+            factory_hdr,
+            req_cmd: ReqCmd {
+                cmd_hdr,
+                xtra_hdr: XtraHdr::Qubit(QubitHdr {
+                    qubit_id: EXTRA_QUBIT_ID,
+                }),
+            },
+        };
+        assert_eq!(valid.validate(), Ok(()));
+        assert_eq!(
+            valid.len(),
+            FactoryHdr::hdr_len() + CmdHdr::hdr_len() + QubitHdr::hdr_len()
+        );
+
+        // A CNOT without a target qubit.
+        let missing = FactoryReq {
+            factory_hdr: FactoryHdr {
+                num_iter: 10,
+                options: FactoryOpt::empty(),
+            },
+            req_cmd: ReqCmd {
+                cmd_hdr: CmdHdr {
+                    qubit_id: QUBIT_ID,
+                    instr: Cmd::Cnot,
+                    options: CmdOpt::empty(),
+                },
+                xtra_hdr: XtraHdr::None,
+            },
+        };
+        assert_eq!(
+            missing.validate(),
+            Err(ValidationError::XtraHdrMismatch(Cmd::Cnot))
+        );
+
+        // A CNOT targeting its own control qubit.
+        let same = FactoryReq {
+            factory_hdr: FactoryHdr {
+                num_iter: 10,
+                options: FactoryOpt::empty(),
+            },
+            req_cmd: ReqCmd {
+                cmd_hdr: CmdHdr {
+                    qubit_id: QUBIT_ID,
+                    instr: Cmd::Cnot,
+                    options: CmdOpt::empty(),
+                },
+                xtra_hdr: XtraHdr::Qubit(QubitHdr { qubit_id: QUBIT_ID }),
+            },
+        };
+        assert_eq!(
+            same.validate(),
+            Err(ValidationError::TargetIsControl(Cmd::Cnot, QUBIT_ID))
+        );
+    }
+
+    // Decode a request that only has a non-zero length indicating follow-up
+    // headers, but it is too short to hold the expected header. This should
+    // return an Error and thus panic on unwrap.
+    #[test]
+    #[should_panic(expected = "invalid length 3, expected CmdHdr")]
+    fn invalid_len() {
+        let msg_type = MsgType::Tp(Tp::Command);
+        let length = CmdHdr::hdr_len() - 1;
+        let instr = Cmd::New;
+        let options = *CmdOpt::empty().set_notify().set_block();
+
+        let expected: Vec<u8> = vec![
+            // CQC header
+            Version::V2 as u8,
+            From::from(msg_type),
+            get_byte_16!(APP_ID, 0),
+            get_byte_16!(APP_ID, 1),
+            get_byte_32!(length, 0),
+            get_byte_32!(length, 1),
+            get_byte_32!(length, 2),
+            get_byte_32!(length, 3),
+            // CMD header
+            get_byte_16!(QUBIT_ID, 0),
+            get_byte_16!(QUBIT_ID, 1),
+            instr as u8,
+            options.bits(),
+        ];
+
+        let decoder = Decoder::new();
+        let _: Request = decoder.decode(&expected[..]).unwrap();
+    }
+
+    // Decode a request that only has an invalid CQC version. This should
+    // return an error naming the offending value.
+    #[test]
+    fn invalid_version() {
+        let msg_type = MsgType::Tp(Tp::Command);
+        let length = CmdHdr::hdr_len();
+        let instr = Cmd::New;
+        let options = *CmdOpt::empty().set_notify().set_block();
+
+        let expected: Vec<u8> = vec![
+            // CQC header
+            Version::V3 as u8 + 1,
+            From::from(msg_type),
+            get_byte_16!(APP_ID, 0),
+            get_byte_16!(APP_ID, 1),
+            get_byte_32!(length, 0),
+            get_byte_32!(length, 1),
+            get_byte_32!(length, 2),
+            get_byte_32!(length, 3),
+            // CMD header
+            get_byte_16!(QUBIT_ID, 0),
+            get_byte_16!(QUBIT_ID, 1),
+            instr as u8,
+            options.bits(),
+        ];
+
+        let decoder = Decoder::new();
+        match decoder.decode::<Request>(&expected[..]) {
+            Err(DecodeError::UnsupportedVersion(value)) => {
+                assert_eq!(value, Version::V3 as u8 + 1)
+            }
+            other => panic!("Expected UnsupportedVersion, got {:?}", other),
+        }
+    }
+
+    // Decode a request that only has an invalid message type. This should
+    // return an error naming the offending value.
+    #[test]
+    fn invalid_msg_type() {
+        let length = CmdHdr::hdr_len();
+        let instr = Cmd::New;
+        let options = *CmdOpt::empty().set_notify().set_block();
+
+        let expected: Vec<u8> = vec![
+            // CQC header
+            Version::V2 as u8,
+            0xFF,
+            get_byte_16!(APP_ID, 0),
+            get_byte_16!(APP_ID, 1),
+            get_byte_32!(length, 0),
+            get_byte_32!(length, 1),
+            get_byte_32!(length, 2),
+            get_byte_32!(length, 3),
+            // CMD header
+            get_byte_16!(QUBIT_ID, 0),
+            get_byte_16!(QUBIT_ID, 1),
+            instr as u8,
+            options.bits(),
+        ];
+
+        let decoder = Decoder::new();
+        match decoder.decode::<Request>(&expected[..]) {
+            Err(DecodeError::InvalidMessageType(value)) => {
+                assert_eq!(value, 0xFF)
+            }
+            other => panic!("Expected InvalidMessageType, got {:?}", other),
+        }
     }
 }
@@ -2,9 +2,13 @@ extern crate cqc;
 
 #[cfg(test)]
 mod response {
-    use cqc::builder::Server;
+    use cqc::builder::{Client, Server, ToResponse};
     use cqc::hdr::*;
-    use cqc::{Decoder, Encoder, Response};
+    use cqc::{
+        CqcPacket, DecodeError, Decoder, Encoder, HeaderKind, Request,
+        Response, RspInfo, ValidationError,
+    };
+    use std::thread;
 
     macro_rules! get_byte_16 {
         ($value:expr, $byte:expr) => {
@@ -72,6 +76,65 @@ mod response {
         assert_eq!(result, response);
     }
 
+    // Round trip a Hello message acknowledging a liveness check.
+    #[test]
+    fn hello_ack() {
+        let server = Server::new(APP_ID);
+        let response = server.hello_ack();
+        assert!(response.is_hello());
+        assert_eq!(response.len(), CqcHdr::hdr_len());
+
+        let encoder = Encoder::new();
+        let buffer = encoder.into_vec(&response);
+        assert_eq!(buffer[1], u8::from(MsgType::Tp(Tp::Hello)));
+
+        let decoder = Decoder::new();
+        let decoded: Response = decoder.decode(&buffer[..]).unwrap();
+        assert!(decoded.is_hello());
+        assert_eq!(decoded, response);
+
+        assert!(!server.done().is_hello());
+    }
+
+    // Round trip an error response.
+    #[test]
+    fn err_rsp() {
+        let server = Server::new(APP_ID);
+        let response = server.err_no_qubit();
+        assert_eq!(response.cqc_hdr.msg_type, MsgType::Err(Err::NoQubit));
+        assert_eq!(response, server.error(Err::NoQubit));
+        assert!(response.notify.is_none());
+
+        let encoder = Encoder::new();
+        let buffer = encoder.into_vec(&response);
+        assert_eq!(buffer.len(), CqcHdr::hdr_len() as usize);
+        assert_eq!(buffer[1], Err::NoQubit as u8);
+
+        let decoder = Decoder::new();
+        let decoded: Response = decoder.decode(&buffer[..]).unwrap();
+        assert_eq!(decoded, response);
+    }
+
+    // Round trip an Unsupp error response naming the offending command.
+    #[test]
+    fn unsupported_rsp() {
+        let server = Server::new(APP_ID);
+        let response = server.unsupported(Cmd::Cphase);
+        assert_eq!(response.cqc_hdr.msg_type, MsgType::Err(Err::Unsupp));
+        assert_eq!(response.len(), CqcHdr::hdr_len() + 1);
+
+        let encoder = Encoder::new();
+        let buffer = encoder.into_vec(&response);
+        assert_eq!(buffer[8], Cmd::Cphase as u8);
+
+        let decoder = Decoder::new();
+        let decoded: Response = decoder.decode(&buffer[..]).unwrap();
+        assert_eq!(decoded.unsupported_command(), Some(Cmd::Cphase));
+
+        assert_eq!(server.err_unsupp().unsupported_command(), None);
+        assert_eq!(server.done().unsupported_command(), None);
+    }
+
     // Decode a response with an Extra Qubit header.
     #[test]
     fn qubit_rsp() {
@@ -111,6 +174,23 @@ mod response {
         assert_eq!(result, response);
     }
 
+    // Decode an Expire notification which carries the ID of the expired
+    // qubit.
+    #[test]
+    fn expire_rsp() {
+        let server = Server::new(APP_ID);
+        let response = server.expire(QUBIT_ID);
+
+        let encoder = Encoder::new();
+        let buffer = encoder.into_vec(&response);
+
+        let decoder = Decoder::new();
+        let result: Response = decoder.decode(&buffer[..]).unwrap();
+        assert_eq!(result, response);
+        assert_eq!(result.validate(), Ok(()));
+        assert_eq!(result.notify.get_qubit_hdr().qubit_id, QUBIT_ID);
+    }
+
     // Decode a response with a Measurement Outcome header.
     #[test]
     fn meas_out_rsp() {
@@ -149,6 +229,22 @@ mod response {
         assert_eq!(result, response);
     }
 
+    // Read the outcome of a measurement response.
+    #[test]
+    fn measurement() {
+        let server = Server::new(APP_ID);
+        let outcome = server.meas_out(MeasOut::One).measurement().unwrap();
+        assert_eq!(outcome, MeasOut::One);
+
+        match server.done().measurement() {
+            Err(DecodeError::UnexpectedResponse { expected, actual }) => {
+                assert_eq!(expected, MsgType::Tp(Tp::MeasOut));
+                assert_eq!(actual, MsgType::Tp(Tp::Done));
+            }
+            other => panic!("Expected UnexpectedResponse, got {:?}", other),
+        }
+    }
+
     // Decode a response that has CQC and Entanglement Info headers.
     #[test]
     fn ent_info_hdr() {
@@ -290,6 +386,442 @@ mod response {
         assert_eq!(result, response);
     }
 
+    // The reply to a GetTime request is an InfTime message.
+    #[test]
+    fn get_time_rsp() {
+        let server = Server::new(APP_ID);
+        let response = server.get_time_response(TIMESTAMP);
+        assert_eq!(response.cqc_hdr.msg_type, MsgType::Tp(Tp::InfTime));
+        assert_eq!(response, server.inf_time(TIMESTAMP));
+
+        match response.notify {
+            RspInfo::Time(ref hdr) => assert_eq!(hdr.datetime, TIMESTAMP),
+            _ => panic!("Expected TimeInfoHdr"),
+        }
+    }
+
+    // GetTime is only a request and InfTime only a response.
+    #[test]
+    fn get_time_direction() {
+        let client = Client::new(APP_ID);
+        let server = Server::new(APP_ID);
+        let encoder = Encoder::new();
+        let decoder = Decoder::new();
+
+        let query = client.get_time(QUBIT_ID);
+        let query_buf = encoder.into_vec(&query);
+        let reply = server.get_time_response(TIMESTAMP);
+        let reply_buf = encoder.into_vec(&reply);
+
+        assert_eq!(decoder.decode_request(&query_buf[..]).unwrap(), query);
+        assert_eq!(decoder.decode_response(&reply_buf[..]).unwrap(), reply);
+
+        match decoder.decode_response(&query_buf[..]) {
+            Err(DecodeError::NotResponse(msg_type)) => {
+                assert_eq!(msg_type, MsgType::Tp(Tp::GetTime))
+            }
+            other => panic!("Expected NotResponse, got {:?}", other),
+        }
+        match decoder.decode_request(&reply_buf[..]) {
+            Err(DecodeError::NotRequest(msg_type)) => {
+                assert_eq!(msg_type, MsgType::Tp(Tp::InfTime))
+            }
+            other => panic!("Expected NotRequest, got {:?}", other),
+        }
+    }
+
+    // Decode the grouped responses of a factory in one call.
+    #[test]
+    fn factory_rsp() {
+        let server = Server::new(APP_ID);
+        let rsps = vec![
+            server.meas_out(MeasOut::Zero),
+            server.meas_out(MeasOut::One),
+            server.done(),
+        ];
+        let response = server.factory(rsps);
+        assert_eq!(
+            response.cqc_hdr.length,
+            2 * (CqcHdr::hdr_len() + MeasOutHdr::hdr_len())
+                + CqcHdr::hdr_len()
+        );
+
+        let encoder = Encoder::new();
+        let buffer = encoder.into_vec(&response);
+        assert_eq!(buffer.len(), response.len() as usize);
+
+        let decoder = Decoder::new();
+        let decoded: Response = decoder.decode(&buffer[..]).unwrap();
+        assert_eq!(decoded, response);
+
+        let rsps = decoded.notify.get_factory();
+        assert_eq!(rsps.len(), 3);
+        assert_eq!(
+            rsps[1].notify,
+            RspInfo::MeasOut(MeasOutHdr {
+                meas_out: MeasOut::One,
+            })
+        );
+
+        // A nested response may not run past the factory length.
+        let mut buffer = buffer;
+        buffer[7] -= 1;
+        let decoded: Result<Response, _> = decoder.decode(&buffer[..]);
+        assert!(decoded.is_err());
+
+        // The length of a nested response is checked before its body is
+        // read.
+        let length = CqcHdr::hdr_len();
+        let buffer: Vec<u8> = vec![
+            Version::V2 as u8,
+            From::from(MsgType::Tp(Tp::Factory)),
+            get_byte_16!(APP_ID, 0),
+            get_byte_16!(APP_ID, 1),
+            get_byte_32!(length, 0),
+            get_byte_32!(length, 1),
+            get_byte_32!(length, 2),
+            get_byte_32!(length, 3),
+            Version::V2 as u8,
+            From::from(MsgType::Tp(Tp::Done)),
+            get_byte_16!(APP_ID, 0),
+            get_byte_16!(APP_ID, 1),
+            0xFF,
+            0xFF,
+            0xFF,
+            0xFF,
+        ];
+        let decoded: Result<Response, _> = decoder.decode(&buffer[..]);
+        assert!(decoded.is_err());
+    }
+
+    // A NewOk response to the allocation of several qubits.
+    #[test]
+    fn new_ok_qubits() {
+        let qubit_ids = [QUBIT_ID, QUBIT_ID + 1, QUBIT_ID + 2];
+        let length = qubit_ids.len() as u32 * QubitHdr::hdr_len();
+
+        let mut expected: Vec<u8> = vec![
+            Version::V2 as u8,
+            From::from(MsgType::Tp(Tp::NewOk)),
+            get_byte_16!(APP_ID, 0),
+            get_byte_16!(APP_ID, 1),
+            get_byte_32!(length, 0),
+            get_byte_32!(length, 1),
+            get_byte_32!(length, 2),
+            get_byte_32!(length, 3),
+        ];
+        for &qubit_id in &qubit_ids {
+            expected.push(get_byte_16!(qubit_id, 0));
+            expected.push(get_byte_16!(qubit_id, 1));
+        }
+
+        let decoder = Decoder::new();
+        let response: Response = decoder.decode(&expected[..]).unwrap();
+        let hdrs: Vec<u16> = response
+            .notify
+            .get_qubits()
+            .iter()
+            .map(|hdr| hdr.qubit_id)
+            .collect();
+        assert_eq!(hdrs, qubit_ids);
+
+        let response: Response = decoder.decode(&expected[..]).unwrap();
+        assert_eq!(response.validate(), Ok(()));
+        assert_eq!(response.headers().len(), 1 + qubit_ids.len());
+
+        let encoder = Encoder::new();
+        assert_eq!(encoder.into_vec(&response), expected);
+
+        // A body which is not a whole number of Qubit Headers is rejected
+        // rather than truncated.
+        let length: u32 = 3;
+        let mut buffer = expected[..CqcHdr::hdr_len() as usize + 3].to_vec();
+        for byte in 0..4 {
+            buffer[4 + byte] = get_byte_32!(length, byte);
+        }
+        let decoded: Result<Response, _> = decoder.decode(&buffer[..]);
+        assert!(decoded.is_err());
+        assert!(decoder.decode_response(&buffer[..]).is_err());
+    }
+
+    // Decode the CQC header first and defer parsing of the body.
+    #[test]
+    fn parse_header() {
+        let server = Server::new(APP_ID);
+        let response = server.new_ok(QUBIT_ID);
+
+        let encoder = Encoder::new();
+        let buffer = encoder.into_vec(&response);
+
+        let decoder = Decoder::new();
+        let (cqc_hdr, body) = decoder.parse_header(&buffer[..]).unwrap();
+        assert_eq!(cqc_hdr, response.cqc_hdr);
+
+        let result: Response = body.parse().unwrap();
+        assert_eq!(result, response);
+
+        // The header is available even if the body is corrupt.
+        let length: u32 = MeasOutHdr::hdr_len();
+        let corrupt: Vec<u8> = vec![
+            Version::V2 as u8,
+            From::from(MsgType::Tp(Tp::MeasOut)),
+            get_byte_16!(APP_ID, 0),
+            get_byte_16!(APP_ID, 1),
+            get_byte_32!(length, 0),
+            get_byte_32!(length, 1),
+            get_byte_32!(length, 2),
+            get_byte_32!(length, 3),
+            0x02,
+        ];
+
+        let (cqc_hdr, body) = decoder.parse_header(&corrupt[..]).unwrap();
+        assert_eq!(cqc_hdr.msg_type, MsgType::Tp(Tp::MeasOut));
+        assert_eq!(cqc_hdr.length, length);
+        assert!(body.parse::<Response>().is_err());
+    }
+
+    // Round-trip a correlation token through a request and its response.
+    #[test]
+    fn correlation() {
+        let token_bits = 4;
+        let app_id = APP_ID & 0x0F_FF;
+        let client = Client::with_correlation(app_id, token_bits);
+
+        let first = client.hello();
+        let second = client.cmd_new(0, CmdOpt::empty());
+        assert_eq!(first.cqc_hdr.app_id, app_id);
+        assert_eq!(second.cqc_hdr.app_id, 0x10_00 | app_id);
+
+        // The backend echoes the application ID of the request.
+        let server = Server::new(second.cqc_hdr.app_id);
+        let encoder = Encoder::new();
+        let buffer = encoder.into_vec(&server.new_ok(QUBIT_ID));
+
+        let decoder = Decoder::new();
+        let response: Response = decoder.decode(&buffer[..]).unwrap();
+        assert_eq!(response.correlation(token_bits), Some(1));
+        assert_eq!(response.cqc_hdr.app_id & 0x0F_FF, app_id);
+        assert_eq!(response.correlation(0), None);
+    }
+
+    // Decode the body of an EPR response given its pre-parsed header.
+    #[test]
+    fn decode_rsp_info() {
+        let server = Server::new(APP_ID);
+        let response = server.epr_ok(
+            QUBIT_ID,
+            EntInfoHdr {
+                node_a: NODE,
+                port_a: PORT,
+                app_id_a: APP_ID,
+                node_b: REMOTE_NODE,
+                port_b: REMOTE_PORT,
+                app_id_b: REMOTE_APP_ID,
+                id_ab: ENT_ID,
+                timestamp: TIMESTAMP,
+                tog: TOG,
+                goodness: GOODNESS,
+                df: 0,
+                align: 0,
+            },
+        );
+
+        let encoder = Encoder::new();
+        let buffer = encoder.into_vec(&response);
+
+        let decoder = Decoder::new();
+        let (cqc_hdr, _) = decoder.parse_header(&buffer[..]).unwrap();
+        assert_eq!(cqc_hdr.msg_type, MsgType::Tp(Tp::EprOk));
+
+        let body = &buffer[CqcHdr::hdr_len() as usize..];
+        let rsp_info = decoder.decode_rsp_info(body, &cqc_hdr).unwrap();
+        assert_eq!(rsp_info, response.notify);
+
+        let ent_info_hdr = rsp_info.get_epr_hdr().ent_info_hdr;
+        assert_eq!(ent_info_hdr.id_ab, ENT_ID);
+
+        // A header without a body.
+        let done = server.done();
+        let rsp_info = decoder.decode_rsp_info(&[], &done.cqc_hdr).unwrap();
+        assert_eq!(rsp_info, RspInfo::None);
+    }
+
+    // Decode a response with a 1 KiB body that is not recognised for its
+    // message type.
+    #[test]
+    fn raw_rsp() {
+        let length: u32 = 1024;
+        let mut buffer: Vec<u8> = vec![
+            Version::V2 as u8,
+            From::from(MsgType::Tp(Tp::Done)),
+            get_byte_16!(APP_ID, 0),
+            get_byte_16!(APP_ID, 1),
+            get_byte_32!(length, 0),
+            get_byte_32!(length, 1),
+            get_byte_32!(length, 2),
+            get_byte_32!(length, 3),
+        ];
+        buffer.extend((0..length).map(|i| i as u8));
+
+        let decoder = Decoder::new();
+        let response: Response = decoder.decode(&buffer[..]).unwrap();
+        assert_eq!(response.len() as usize, buffer.len());
+        let raw = response.notify.get_raw();
+        assert_eq!(raw.len(), length as usize);
+        assert_eq!(&raw[..], &buffer[CqcHdr::hdr_len() as usize..]);
+
+        // The response decoder copies the body straight out of the buffer.
+        let response = decoder.decode_response(&buffer[..]).unwrap();
+        let raw = response.notify.get_raw();
+        assert_eq!(&raw[..], &buffer[CqcHdr::hdr_len() as usize..]);
+
+        // A truncated body is an error.
+        buffer.pop();
+        assert!(decoder.decode::<Response>(&buffer[..]).is_err());
+    }
+
+    // Track entanglement IDs across two EPR responses where the second ID
+    // regresses.
+    #[test]
+    fn ent_id_tracking() {
+        let server = Server::new(APP_ID);
+        let epr_ok = |id_ab| {
+            server.epr_ok(
+                QUBIT_ID,
+                EntInfoHdr {
+                    node_a: NODE,
+                    port_a: PORT,
+                    app_id_a: APP_ID,
+                    node_b: REMOTE_NODE,
+                    port_b: REMOTE_PORT,
+                    app_id_b: REMOTE_APP_ID,
+                    id_ab,
+                    timestamp: TIMESTAMP,
+                    tog: TOG,
+                    goodness: GOODNESS,
+                    df: 0,
+                    align: 0,
+                },
+            )
+        };
+
+        let encoder = Encoder::new();
+        let first = encoder.into_vec(&epr_ok(ENT_ID));
+        let second = encoder.into_vec(&epr_ok(ENT_ID - 1));
+
+        // A plain decoder does not track IDs.
+        let decoder = Decoder::new();
+        assert!(decoder.decode_response(&first[..]).is_ok());
+        assert!(decoder.decode_response(&second[..]).is_ok());
+
+        // The tracking decoder can be shared between threads.
+        let decoder = Decoder::with_ent_id_tracking();
+        let result = thread::scope(|scope| {
+            scope
+                .spawn(|| decoder.decode_response(&first[..]))
+                .join()
+                .unwrap()
+        });
+        assert_eq!(result.unwrap(), epr_ok(ENT_ID));
+        match decoder.decode_response(&second[..]) {
+            Err(DecodeError::NonMonotonicEntId {
+                node_a,
+                node_b,
+                last,
+                id,
+            }) => {
+                assert_eq!((node_a, node_b), (NODE, REMOTE_NODE));
+                assert_eq!((last, id), (ENT_ID, ENT_ID - 1));
+            }
+            other => panic!("Expected NonMonotonicEntId, got {:?}", other),
+        }
+
+        // Every decoding function checks the IDs, also inside a factory.
+        let decoder = Decoder::with_ent_id_tracking();
+        let result: Result<Response, _> = decoder.decode(&first[..]);
+        assert!(result.is_ok());
+        let result: Result<Response, _> = decoder.decode_from(&second[..]);
+        match result {
+            Err(DecodeError::NonMonotonicEntId { .. }) => (),
+            other => panic!("Expected NonMonotonicEntId, got {:?}", other),
+        }
+        let factory =
+            encoder.into_vec(&server.factory(vec![epr_ok(ENT_ID - 1)]));
+        match decoder.decode_response(&factory[..]) {
+            Err(DecodeError::NonMonotonicEntId { .. }) => (),
+            other => panic!("Expected NonMonotonicEntId, got {:?}", other),
+        }
+    }
+
+    // Nodes which share an IP address, as they do in SimulaQron, are told
+    // apart by their ports when tracking entanglement IDs.
+    #[test]
+    fn ent_id_tracking_localhost() {
+        let localhost = 0x7F_00_00_01;
+        let server = Server::new(APP_ID);
+        let epr_ok = |port_b, id_ab| {
+            server.epr_ok(
+                QUBIT_ID,
+                EntInfoHdr {
+                    node_a: localhost,
+                    port_a: 8801,
+                    app_id_a: APP_ID,
+                    node_b: localhost,
+                    port_b,
+                    app_id_b: REMOTE_APP_ID,
+                    id_ab,
+                    timestamp: TIMESTAMP,
+                    tog: TOG,
+                    goodness: GOODNESS,
+                    df: 0,
+                    align: 0,
+                },
+            )
+        };
+
+        let encoder = Encoder::new();
+        let decoder = Decoder::with_ent_id_tracking();
+        for &(port_b, id_ab) in &[(8803, 5), (8804, 1), (8803, 6), (8804, 2)] {
+            let buffer = encoder.into_vec(&epr_ok(port_b, id_ab));
+            let response = decoder.decode_response(&buffer[..]).unwrap();
+            assert_eq!(response, epr_ok(port_b, id_ab));
+        }
+    }
+
+    // Describe an entanglement record between two localhost nodes.
+    #[cfg(feature = "resolve")]
+    #[test]
+    fn describe_entanglement() {
+        let localhost = 0x7F_00_00_01;
+        let server = Server::new(APP_ID);
+        let response = server.epr_ok(
+            QUBIT_ID,
+            EntInfoHdr {
+                node_a: localhost,
+                port_a: PORT,
+                app_id_a: APP_ID,
+                node_b: localhost,
+                port_b: REMOTE_PORT,
+                app_id_b: REMOTE_APP_ID,
+                id_ab: ENT_ID,
+                timestamp: TIMESTAMP,
+                tog: TOG,
+                goodness: GOODNESS,
+                df: 0,
+                align: 0,
+            },
+        );
+
+        let description = response.describe_entanglement();
+        assert!(description.contains("localhost"), "{}", description);
+        assert!(description.contains(&format!(":{}", PORT)));
+        assert!(description.contains(&format!(":{}", REMOTE_PORT)));
+        assert!(description.contains(&ENT_ID.to_string()));
+
+        let description = server.done().describe_entanglement();
+        assert!(!description.contains("localhost"));
+    }
+
     // Test an encoding when the provided buffer is too small (should panic).
     #[test]
     #[should_panic(expected = "failed to write whole buffer")]
@@ -333,7 +865,7 @@ mod response {
         // Buffer to write into.
         let write_len: usize = response.len() as usize;
         let buf_len: usize = write_len + 4;
-        let mut buffer = vec![0xAA; buf_len as usize];
+        let mut buffer = vec![0xAA; buf_len];
 
         // Expected values
         let msg_type = MsgType::Tp(Tp::Done);
@@ -393,16 +925,43 @@ mod response {
         let _: Response = decoder.decode(&expected[..]).unwrap();
     }
 
+    // Decode a response from an older backend that reports version 1.
+    #[test]
+    fn version_1() {
+        let length: u32 = QubitHdr::hdr_len();
+        let buffer: Vec<u8> = vec![
+            // CQC header
+            Version::V1 as u8,
+            From::from(MsgType::Tp(Tp::NewOk)),
+            get_byte_16!(APP_ID, 0),
+            get_byte_16!(APP_ID, 1),
+            get_byte_32!(length, 0),
+            get_byte_32!(length, 1),
+            get_byte_32!(length, 2),
+            get_byte_32!(length, 3),
+            // Qubit header
+            get_byte_16!(QUBIT_ID, 0),
+            get_byte_16!(QUBIT_ID, 1),
+        ];
+
+        let decoder = Decoder::new();
+        let response: Response = decoder.decode(&buffer[..]).unwrap();
+        assert_eq!(response.cqc_hdr.version, Version::V1);
+        assert_eq!(
+            response.notify,
+            RspInfo::Qubit(QubitHdr { qubit_id: QUBIT_ID })
+        );
+    }
+
     // Decode a response that only has an invalid CQC version. This should
-    // return an error (and thus panic on an unwrap).
+    // return an error naming the offending value.
     #[test]
-    #[should_panic(expected = "Invalid CQC version")]
     fn invalid_version() {
         let msg_type = MsgType::Tp(Tp::Done);
         let length: u32 = 0;
 
         let expected: Vec<u8> = vec![
-            Version::V2 as u8 + 1,
+            Version::V3 as u8 + 1,
             From::from(msg_type),
             get_byte_16!(APP_ID, 1),
             get_byte_16!(APP_ID, 0),
@@ -413,13 +972,17 @@ mod response {
         ];
 
         let decoder = Decoder::new();
-        let _: Response = decoder.decode(&expected[..]).unwrap();
+        match decoder.decode::<Response>(&expected[..]) {
+            Err(DecodeError::UnsupportedVersion(value)) => {
+                assert_eq!(value, Version::V3 as u8 + 1)
+            }
+            other => panic!("Expected UnsupportedVersion, got {:?}", other),
+        }
     }
 
     // Decode a response that only has an invalid message type. This should
-    // return an error (and thus panic on an unwrap).
+    // return an error naming the offending value.
     #[test]
-    #[should_panic(expected = "Invalid CQC message type")]
     fn invalid_msg_type() {
         let length: u32 = 0;
 
@@ -435,6 +998,210 @@ mod response {
         ];
 
         let decoder = Decoder::new();
-        let _: Response = decoder.decode(&expected[..]).unwrap();
+        match decoder.decode::<Response>(&expected[..]) {
+            Err(DecodeError::InvalidMessageType(value)) => {
+                assert_eq!(value, 0xFF)
+            }
+            other => panic!("Expected InvalidMessageType, got {:?}", other),
+        }
+    }
+
+    // A lenient decoder returns a response of an unknown message type with
+    // its body as raw bytes.
+    #[test]
+    fn lenient_msg_type() {
+        let length: u32 = 3;
+
+        let mut buffer: Vec<u8> = vec![
+            Version::V2 as u8,
+            0x1E,
+            get_byte_16!(APP_ID, 0),
+            get_byte_16!(APP_ID, 1),
+            get_byte_32!(length, 0),
+            get_byte_32!(length, 1),
+            get_byte_32!(length, 2),
+            get_byte_32!(length, 3),
+            0x01,
+            0x02,
+            0x03,
+        ];
+
+        let decoder = Decoder::lenient();
+        let response = decoder.decode_response(&buffer[..]).unwrap();
+        assert_eq!(response.cqc_hdr.msg_type, MsgType::Other(0x1E));
+        assert_eq!(response.cqc_hdr.app_id, APP_ID);
+        assert_eq!(response.notify, RspInfo::Raw(vec![0x01, 0x02, 0x03]));
+        assert_eq!(response.validate(), Ok(()));
+
+        let encoder = Encoder::new();
+        assert_eq!(encoder.into_vec(&response), buffer);
+
+        match decoder.decode_any(&buffer[..]).unwrap() {
+            CqcPacket::Response(rsp) => assert_eq!(rsp, response),
+            other => panic!("Expected a response, got {:?}", other),
+        }
+
+        // The generic decoding functions are lenient too.
+        let decoded: Response = decoder.decode(&buffer[..]).unwrap();
+        assert_eq!(decoded, response);
+        let decoded: Response = decoder.decode_from(&buffer[..]).unwrap();
+        assert_eq!(decoded, response);
+        let decoded: Option<(Response, usize)> =
+            decoder.decode_partial(&buffer[..]).unwrap();
+        assert_eq!(decoded, Some((response, buffer.len())));
+        assert!(decoder.decode::<Request>(&buffer[..]).is_err());
+
+        // The default decoder still rejects the message type.
+        match Decoder::new().decode_response(&buffer[..]) {
+            Err(DecodeError::InvalidMessageType(value)) => {
+                assert_eq!(value, 0x1E)
+            }
+            other => panic!("Expected InvalidMessageType, got {:?}", other),
+        }
+        match Decoder::new().decode::<Response>(&buffer[..]) {
+            Err(DecodeError::InvalidMessageType(value)) => {
+                assert_eq!(value, 0x1E)
+            }
+            other => panic!("Expected InvalidMessageType, got {:?}", other),
+        }
+
+        // A truncated body is still an error.
+        buffer.pop();
+        match decoder.decode_response(&buffer[..]) {
+            Err(DecodeError::Truncated { needed, got }) => {
+                assert_eq!((needed, got), (11, 10))
+            }
+            other => panic!("Expected Truncated, got {:?}", other),
+        }
+    }
+
+    // A user measurement type that converts into a response.
+    struct Outcome(bool);
+
+    impl ToResponse for Outcome {
+        fn to_response(&self, server: &Server) -> Response {
+            let meas_out = if self.0 { MeasOut::One } else { MeasOut::Zero };
+            server.meas_out(meas_out)
+        }
+    }
+
+    #[test]
+    fn to_response() {
+        let server = Server::new(APP_ID);
+        let response = Outcome(true).to_response(&server);
+        assert_eq!(response, server.meas_out(MeasOut::One));
+    }
+
+    // List the headers of an EPR response.
+    #[test]
+    fn headers() {
+        let server = Server::new(APP_ID);
+        let response = server.epr_ok(
+            QUBIT_ID,
+            EntInfoHdr {
+                node_a: NODE,
+                port_a: PORT,
+                app_id_a: APP_ID,
+                node_b: REMOTE_NODE,
+                port_b: REMOTE_PORT,
+                app_id_b: REMOTE_APP_ID,
+                id_ab: ENT_ID,
+                timestamp: TIMESTAMP,
+                tog: TOG,
+                goodness: GOODNESS,
+                df: 0,
+                align: 0,
+            },
+        );
+
+        assert_eq!(
+            response.headers(),
+            vec![HeaderKind::Cqc, HeaderKind::Qubit, HeaderKind::EntInfo]
+        );
+        assert_eq!(server.done().headers(), vec![HeaderKind::Cqc]);
+    }
+
+    #[test]
+    fn validate() {
+        let server = Server::new(APP_ID);
+        assert_eq!(server.new_ok(QUBIT_ID).validate(), Ok(()));
+        assert_eq!(server.done().validate(), Ok(()));
+        assert_eq!(server.unsupported(Cmd::Epr).validate(), Ok(()));
+        assert_eq!(
+            server
+                .factory(vec![server.new_ok(QUBIT_ID), server.done()])
+                .validate(),
+            Ok(())
+        );
+
+        let mut wrong_len = server.meas_out(MeasOut::One);
+        wrong_len.cqc_hdr.length += 1;
+        assert_eq!(
+            wrong_len.validate(),
+            Err(ValidationError::LengthMismatch {
+                declared: MeasOutHdr::hdr_len() + 1,
+                actual: MeasOutHdr::hdr_len(),
+            })
+        );
+
+        let mut wrong_body = server.new_ok(QUBIT_ID);
+        wrong_body.cqc_hdr.msg_type = MsgType::Tp(Tp::EprOk);
+        assert_eq!(
+            wrong_body.validate(),
+            Err(ValidationError::NotifyMismatch(MsgType::Tp(Tp::EprOk)))
+        );
+
+        let mut nested = server.new_ok(QUBIT_ID);
+        nested.cqc_hdr.msg_type = MsgType::Tp(Tp::MeasOut);
+        assert_eq!(
+            server.factory(vec![nested]).validate(),
+            Err(ValidationError::NotifyMismatch(MsgType::Tp(Tp::MeasOut)))
+        );
+    }
+
+    #[test]
+    fn msg_type() {
+        let server = Server::new(APP_ID);
+        let response = server.done();
+        assert_eq!(response.msg_type(), MsgType::Tp(Tp::Done));
+        assert!(!response.is_error());
+
+        let response = server.err_timeout();
+        assert_eq!(response.msg_type(), MsgType::Err(Err::Timeout));
+        assert!(response.is_error());
+
+        let client = Client::new(APP_ID);
+        assert_eq!(client.hello().msg_type(), MsgType::Tp(Tp::Hello));
+    }
+
+    #[test]
+    fn epr_ok_between() {
+        use std::net::{Ipv4Addr, SocketAddrV4};
+
+        let server = Server::new(APP_ID);
+        let a = SocketAddrV4::new(Ipv4Addr::from(NODE), PORT);
+        let b = SocketAddrV4::new(Ipv4Addr::from(REMOTE_NODE), REMOTE_PORT);
+        let response = server.epr_ok_between(
+            QUBIT_ID,
+            a,
+            b,
+            ENT_ID,
+            GOODNESS,
+            Directionality::NodeA,
+        );
+        assert_eq!(response.validate(), Ok(()));
+
+        let epr = response.notify.get_epr_hdr();
+        assert_eq!(epr.qubit_hdr.qubit_id, QUBIT_ID);
+
+        let ent_info = epr.ent_info_hdr;
+        assert_eq!(ent_info.addr_a(), a);
+        assert_eq!(ent_info.addr_b(), b);
+        assert_eq!(ent_info.id_ab, ENT_ID);
+        assert_eq!(ent_info.goodness, GOODNESS);
+        assert_eq!(ent_info.directionality(), Some(Directionality::NodeA));
+        assert_eq!(ent_info.align, 0);
+        assert!(ent_info.timestamp > 0);
+        assert_eq!(ent_info.tog, ent_info.timestamp);
     }
 }
@@ -0,0 +1,316 @@
+extern crate bincode;
+extern crate cqc;
+#[macro_use]
+extern crate quickcheck;
+extern crate serde;
+
+// Property tests asserting that every header and packet decodes back to the
+// value it was encoded from.
+#[cfg(test)]
+mod roundtrip {
+    use bincode;
+    use cqc::builder::{Client, Server};
+    use cqc::hdr::*;
+    use cqc::{Decoder, Encoder, ReqCmd, Request, Response, XtraHdr};
+    use quickcheck::{Arbitrary, Gen};
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+
+    /// An arbitrary value of a type from the `cqc` crate.
+    #[derive(Clone, Debug)]
+    struct Arb<T>(T);
+
+    /// Draw bytes until one converts to a valid enum value.
+    fn pick<T>(g: &mut Gen, get: fn(u8) -> Option<T>) -> T {
+        loop {
+            if let Some(value) = get(u8::arbitrary(g)) {
+                return value;
+            }
+        }
+    }
+
+    macro_rules! arbitrary_hdr {
+        ($hdr: ident, $g: ident => $value: expr) => {
+            impl Arbitrary for Arb<$hdr> {
+                fn arbitrary($g: &mut Gen) -> Self {
+                    Arb($value)
+                }
+            }
+        };
+    }
+
+    arbitrary_hdr!(CqcHdr, g => CqcHdr {
+        version: pick(g, Version::get),
+        msg_type: pick(g, MsgType::get),
+        app_id: u16::arbitrary(g),
+        length: u32::arbitrary(g),
+    });
+
+    arbitrary_hdr!(CmdHdr, g => CmdHdr {
+        qubit_id: u16::arbitrary(g),
+        instr: pick(g, Cmd::get),
+        options: CmdOpt::from_bits_truncate(u8::arbitrary(g)),
+    });
+
+    arbitrary_hdr!(AssignHdr, g => AssignHdr {
+        ref_id: u32::arbitrary(g),
+    });
+
+    arbitrary_hdr!(RotHdr, g => RotHdr {
+        step: u8::arbitrary(g),
+    });
+
+    arbitrary_hdr!(QubitHdr, g => QubitHdr {
+        qubit_id: u16::arbitrary(g),
+    });
+
+    arbitrary_hdr!(SeqHdr, g => SeqHdr {
+        cmd_length: u8::arbitrary(g),
+    });
+
+    arbitrary_hdr!(CommHdr, g => CommHdr {
+        remote_app_id: u16::arbitrary(g),
+        remote_port: u16::arbitrary(g),
+        remote_node: u32::arbitrary(g),
+    });
+
+    arbitrary_hdr!(FactoryHdr, g => FactoryHdr {
+        num_iter: u8::arbitrary(g),
+        options: FactoryOpt::from_bits_truncate(u8::arbitrary(g)),
+    });
+
+    arbitrary_hdr!(MeasOutHdr, g => MeasOutHdr {
+        meas_out: pick(g, MeasOut::get),
+    });
+
+    arbitrary_hdr!(TimeInfoHdr, g => TimeInfoHdr {
+        datetime: u64::arbitrary(g),
+    });
+
+    arbitrary_hdr!(EntInfoHdr, g => EntInfoHdr {
+        node_a: u32::arbitrary(g),
+        port_a: u16::arbitrary(g),
+        app_id_a: u16::arbitrary(g),
+        node_b: u32::arbitrary(g),
+        port_b: u16::arbitrary(g),
+        app_id_b: u16::arbitrary(g),
+        id_ab: u32::arbitrary(g),
+        timestamp: u64::arbitrary(g),
+        tog: u64::arbitrary(g),
+        goodness: u16::arbitrary(g),
+        df: u8::arbitrary(g),
+        align: u8::arbitrary(g),
+    });
+
+    arbitrary_hdr!(TypeHdr, g => TypeHdr {
+        hdr_type: pick(g, Tp::get),
+        length: u32::arbitrary(g),
+    });
+
+    arbitrary_hdr!(IfHdr, g => IfHdr {
+        left_op: u32::arbitrary(g),
+        operator: pick(g, CmpType::get),
+        right_op_t: pick(g, OpType::get),
+        right_op: u32::arbitrary(g),
+        length: u32::arbitrary(g),
+    });
+
+    /// Encode a single header and decode it again.
+    #[allow(deprecated)]
+    fn hdr_round_trip<T>(hdr: &T) -> bool
+    where
+        T: Serialize + DeserializeOwned + PartialEq,
+    {
+        let buffer = Encoder::new().into_vec(hdr);
+        let decoded: T =
+            bincode::config().big_endian().deserialize(&buffer).unwrap();
+        decoded == *hdr
+    }
+
+    /// Build a command with the extra header its instruction requires.  The
+    /// extra headers are taken from `xtra`.
+    fn req_cmd(cmd_hdr: CmdHdr, xtra: &Xtra, in_mix: bool) -> ReqCmd {
+        let xtra_hdr = match cmd_hdr.instr {
+            Cmd::RotX | Cmd::RotY | Cmd::RotZ => XtraHdr::Rot(xtra.0.clone()),
+            Cmd::Cnot | Cmd::Cphase => XtraHdr::Qubit(xtra.1.clone()),
+            Cmd::Send | Cmd::Epr => XtraHdr::Comm(xtra.2.clone()),
+            Cmd::Measure | Cmd::MeasureInplace if in_mix => {
+                XtraHdr::Assign(xtra.3.clone())
+            }
+            _ => XtraHdr::None,
+        };
+
+        ReqCmd { cmd_hdr, xtra_hdr }
+    }
+
+    type Xtra = (RotHdr, QubitHdr, CommHdr, AssignHdr);
+
+    /// Encode a packet and decode it again.
+    fn packet_round_trip<T>(packet: &T) -> bool
+    where
+        T: Serialize + DeserializeOwned + PartialEq,
+    {
+        let buffer = Encoder::new().into_vec(packet);
+        let decoded: T = Decoder::new().decode(&buffer).unwrap();
+        decoded == *packet
+    }
+
+    /// Build one of the responses the backend sends from arbitrary headers.
+    fn response(
+        server: &Server,
+        kind: u8,
+        qubit_hdr: &QubitHdr,
+        meas_out_hdr: &MeasOutHdr,
+        ent_info_hdr: &EntInfoHdr,
+        time_info_hdr: &TimeInfoHdr,
+    ) -> Response {
+        match kind % 7 {
+            0 => server.new_ok(qubit_hdr.qubit_id),
+            1 => server.recv(qubit_hdr.qubit_id),
+            2 => server.meas_out(meas_out_hdr.meas_out),
+            3 => server.epr_ok(qubit_hdr.qubit_id, ent_info_hdr.clone()),
+            4 => server.inf_time(time_info_hdr.datetime),
+            5 => server.expire(qubit_hdr.qubit_id),
+            _ => server.done(),
+        }
+    }
+
+    quickcheck! {
+        fn cqc_hdr(hdr: Arb<CqcHdr>) -> bool {
+            hdr_round_trip(&hdr.0)
+        }
+
+        fn cmd_hdr(hdr: Arb<CmdHdr>) -> bool {
+            hdr_round_trip(&hdr.0)
+        }
+
+        fn assign_hdr(hdr: Arb<AssignHdr>) -> bool {
+            hdr_round_trip(&hdr.0)
+        }
+
+        fn rot_hdr(hdr: Arb<RotHdr>) -> bool {
+            hdr_round_trip(&hdr.0)
+        }
+
+        fn qubit_hdr(hdr: Arb<QubitHdr>) -> bool {
+            hdr_round_trip(&hdr.0)
+        }
+
+        fn seq_hdr(hdr: Arb<SeqHdr>) -> bool {
+            hdr_round_trip(&hdr.0)
+        }
+
+        fn comm_hdr(hdr: Arb<CommHdr>) -> bool {
+            hdr_round_trip(&hdr.0)
+        }
+
+        fn factory_hdr(hdr: Arb<FactoryHdr>) -> bool {
+            hdr_round_trip(&hdr.0)
+        }
+
+        fn meas_out_hdr(hdr: Arb<MeasOutHdr>) -> bool {
+            hdr_round_trip(&hdr.0)
+        }
+
+        fn time_info_hdr(hdr: Arb<TimeInfoHdr>) -> bool {
+            hdr_round_trip(&hdr.0)
+        }
+
+        fn ent_info_hdr(hdr: Arb<EntInfoHdr>) -> bool {
+            hdr_round_trip(&hdr.0)
+        }
+
+        fn type_hdr(hdr: Arb<TypeHdr>) -> bool {
+            hdr_round_trip(&hdr.0)
+        }
+
+        fn if_hdr(hdr: Arb<IfHdr>) -> bool {
+            hdr_round_trip(&hdr.0)
+        }
+
+        fn command(
+            app_id: u16,
+            cmd_hdrs: Vec<Arb<CmdHdr>>,
+            xtra: (Arb<RotHdr>, Arb<QubitHdr>, Arb<CommHdr>, Arb<AssignHdr>)
+        ) -> bool {
+            if cmd_hdrs.is_empty() {
+                return true;
+            }
+
+            let xtra = ((xtra.0).0, (xtra.1).0, (xtra.2).0, (xtra.3).0);
+            let cmds = cmd_hdrs
+                .into_iter()
+                .map(|hdr| req_cmd(hdr.0, &xtra, false))
+                .collect();
+            let request = Client::new(app_id).sequence(cmds).unwrap();
+            packet_round_trip(&request)
+        }
+
+        fn factory(
+            app_id: u16,
+            factory_hdr: Arb<FactoryHdr>,
+            cmd_hdr: Arb<CmdHdr>,
+            xtra: (Arb<RotHdr>, Arb<QubitHdr>, Arb<CommHdr>, Arb<AssignHdr>)
+        ) -> bool {
+            let xtra = ((xtra.0).0, (xtra.1).0, (xtra.2).0, (xtra.3).0);
+            let request: Request = Client::new(app_id).factory(
+                factory_hdr.0.num_iter,
+                factory_hdr.0.options,
+                req_cmd(cmd_hdr.0, &xtra, false),
+            );
+            packet_round_trip(&request)
+        }
+
+        fn mix(
+            app_id: u16,
+            segs: Vec<(Arb<CmdHdr>, Option<Arb<IfHdr>>)>,
+            xtra: (Arb<RotHdr>, Arb<QubitHdr>, Arb<CommHdr>, Arb<AssignHdr>)
+        ) -> bool {
+            if segs.is_empty() {
+                return true;
+            }
+
+            let xtra = ((xtra.0).0, (xtra.1).0, (xtra.2).0, (xtra.3).0);
+            let client = Client::new(app_id);
+            let mut mix = client.mix();
+            for (cmd_hdr, if_hdr) in segs {
+                let req_cmd = req_cmd(cmd_hdr.0, &xtra, true);
+                mix = match if_hdr {
+                    Some(if_hdr) => mix.push_if(if_hdr.0, req_cmd),
+                    None => mix.push_command(req_cmd),
+                };
+            }
+            packet_round_trip(&mix.build())
+        }
+
+        fn response_factory(
+            app_id: u16,
+            kinds: Vec<u8>,
+            hdrs: (
+                Arb<QubitHdr>,
+                Arb<MeasOutHdr>,
+                Arb<EntInfoHdr>,
+                Arb<TimeInfoHdr>
+            )
+        ) -> bool {
+            let server = Server::new(app_id);
+            let rsps: Vec<Response> = kinds
+                .iter()
+                .map(|&kind| {
+                    response(
+                        &server,
+                        kind,
+                        &(hdrs.0).0,
+                        &(hdrs.1).0,
+                        &(hdrs.2).0,
+                        &(hdrs.3).0,
+                    )
+                })
+                .collect();
+
+            rsps.iter().all(packet_round_trip)
+                && (rsps.is_empty()
+                    || packet_round_trip(&server.factory(rsps)))
+        }
+    }
+}